@@ -0,0 +1,112 @@
+use nalgebra::Vector3;
+
+use crate::ray::Ray;
+
+#[cfg(test)]
+use crate::parameters::INF;
+
+/// Axis-aligned bounding box, stored as its two extreme corners.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Build the box spanned by two arbitrary corners.
+    pub fn from_points(a: Vector3<f32>, b: Vector3<f32>) -> Aabb {
+        Aabb { min: a.inf(&b), max: a.sup(&b) }
+    }
+
+    /// Inverted box that any `surrounding` call will overwrite, used as the
+    /// starting point when growing a box over a set of objects.
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vector3::new(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+
+    /// Smallest box containing both `self` and `other`.
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.inf(&other.min), max: self.max.sup(&other.max) }
+    }
+
+    /// Smallest box containing both `self` and the point `p`.
+    pub fn enclose(&self, p: &Vector3<f32>) -> Aabb {
+        Aabb { min: self.min.inf(p), max: self.max.sup(p) }
+    }
+
+    /// Grow the flat dimensions of the box to at least `delta`, so that planar
+    /// objects still get a box the slab test can hit.
+    pub fn padded(&self, delta: f32) -> Aabb {
+        let mut min = self.min;
+        let mut max = self.max;
+        for axis in 0..3 {
+            if max[axis] - min[axis] < delta {
+                min[axis] -= delta / 2.;
+                max[axis] += delta / 2.;
+            }
+        }
+        Aabb { min, max }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        if d.x < 0. || d.y < 0. || d.z < 0. {
+            return 0.
+        }
+        2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn centroid(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Index of the axis along which the box is the widest.
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test: does the ray cross the box somewhere in ]t_min, t_max[ ?
+    pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1. / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max <= t_min {
+                return false
+            }
+        }
+        true
+    }
+}
+
+#[test]
+fn test_aabb_hit() {
+    let aabb = Aabb::from_points(Vector3::new(1., 1., 1.), Vector3::new(-1., -1., -1.));
+    let through = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+    let beside = Ray::new(Vector3::new(2., 0., -5.), Vector3::new(0., 0., 1.));
+    let behind = Ray::new(Vector3::new(0., 0., 5.), Vector3::new(0., 0., 1.));
+
+    assert!(aabb.hit(&through, 0., INF));
+    assert!(!aabb.hit(&beside, 0., INF));
+    assert!(!aabb.hit(&behind, 0., INF));
+    assert!(!aabb.hit(&through, 0., 3.));
+}
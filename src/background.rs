@@ -0,0 +1,128 @@
+use std::{f32::consts::PI, path::Path};
+use nalgebra::Vector3;
+
+use crate::{color::*, image::Image, image_io::{read_image, ImageError}};
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+/// Top of the sky gradient of the book.
+pub const SKY_BLUE: Color = Color { r: 0.5, g: 0.7, b: 1.0 };
+
+/// Radiance coming from infinitely far away, seen by the rays that leave
+/// the scene.
+#[derive(Debug, Clone)]
+pub enum Background {
+    Color(Color),
+    /// Vertical gradient, from `bottom` looking down to `top` looking up.
+    Gradient { bottom: Color, top: Color },
+    Environment(EnvironmentMap),
+}
+
+impl Background {
+    /// The gradient of the book, from white to light blue.
+    pub fn sky() -> Background {
+        Background::Gradient { bottom: WHITE, top: SKY_BLUE }
+    }
+
+    pub fn color(&self, direction: &Vector3<f32>) -> Color {
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.normalize().y + 1.);
+                bottom.scale(1. - t) + top.scale(t)
+            }
+            Background::Environment(map) => map.color(direction),
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Color(BLACK)
+    }
+}
+
+/// Latitude and longitude map of the radiance around the scene, laid out as
+/// rendered by the equirectangular camera: -Z at the center, +Y at the top.
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    pub image: Image,
+    /// Rotation around Y, in radians, turning as `RotateY` does.
+    pub rotation: f32,
+    /// Scale of the radiance of the map.
+    pub intensity: f32,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Image) -> EnvironmentMap {
+        EnvironmentMap { image, rotation: 0., intensity: 1. }
+    }
+
+    /// Load an equirectangular image (PFM, HDR, or sRGB PPM/PNG).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EnvironmentMap, ImageError> {
+        let image = read_image(path)?;
+        let pixels = if image.linear {
+            image.pixels
+        } else {
+            image.pixels.iter().map(Color::srgb_to_linear).collect()
+        };
+        Ok(EnvironmentMap::new(Image::from_pixels(image.width, image.height, pixels)))
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> EnvironmentMap {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> EnvironmentMap {
+        self.intensity = intensity;
+        self
+    }
+
+    // Texel with the longitude wrapping around and the latitude clamped
+    fn texel(&self, i: i64, j: i64) -> Color {
+        let i = i.rem_euclid(self.image.width as i64) as usize;
+        let j = j.clamp(0, self.image.height as i64 - 1) as usize;
+        self.image.get(i, j)
+    }
+
+    pub fn color(&self, direction: &Vector3<f32>) -> Color {
+        if self.image.pixels.is_empty() {
+            return BLACK
+        }
+        let direction = direction.normalize();
+        let longitude = direction.x.atan2(-direction.z) + self.rotation;
+        let latitude = direction.y.clamp(-1., 1.).asin();
+
+        // Bilinear interpolation between the texel centers
+        let x = (0.5 + longitude / (2. * PI)) * self.image.width as f32 - 0.5;
+        let y = (0.5 - latitude / PI) * self.image.height as f32 - 0.5;
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+        let (s, t) = (x - x.floor(), y - y.floor());
+        let top = self.texel(i, j).scale(1. - s) + self.texel(i + 1, j).scale(s);
+        let bottom = self.texel(i, j + 1).scale(1. - s) + self.texel(i + 1, j + 1).scale(s);
+        (top.scale(1. - t) + bottom.scale(t)).scale(self.intensity)
+    }
+}
+
+#[test]
+fn test_environment_map() {
+    // Four columns facing +Z, -X, -Z and +X from left to right
+    let colors = [Color::new(1., 0., 0.), Color::new(0., 1., 0.), Color::new(0., 0., 1.), Color::new(1., 1., 1.)];
+    let image = Image::from_pixels(8, 1, (0..8).map(|i| colors[(i as usize).div_ceil(2) % 4]).collect());
+    let map = EnvironmentMap::new(image).with_intensity(2.);
+    assert_approx_eq!(map.color(&Vector3::new(0., 0., -1.)).b, 2.);
+    assert_approx_eq!(map.color(&Vector3::new(-1., 0., 0.)).g, 2.);
+    assert_approx_eq!(map.color(&Vector3::new(0., 0., 1.)).r, 2.);
+    assert_approx_eq!(map.color(&Vector3::new(1., 0., 0.)).g, 2.);
+
+    // A quarter turn brings the +X column in front
+    let map = map.with_rotation(PI / 2.);
+    assert_approx_eq!(map.color(&Vector3::new(0., 0., -1.)).r, 2.);
+    assert_approx_eq!(map.color(&Vector3::new(0., 0., 1.)).g, 2.);
+
+    let sky = Background::sky();
+    assert_approx_eq!(sky.color(&Vector3::new(0., -1., 0.)).r, 1.);
+    assert_approx_eq!(sky.color(&Vector3::new(0., 3., 0.)).r, 0.5);
+}
@@ -0,0 +1,284 @@
+use crate::{ray::*, aabb::Aabb, primitives::Primitive, parameters::ORIGIN};
+
+#[cfg(test)]
+use nalgebra::Vector3;
+#[cfg(test)]
+use crate::{primitives::Sphere, material::*, color::Color, parameters::{EPSILON, INF}};
+
+/// Tuning knobs of the surface area heuristic.
+#[derive(Debug, Clone, Copy)]
+pub struct BvhParams {
+    /// A node holding this many objects or fewer may become a leaf
+    pub max_leaf_size: usize,
+    /// Number of buckets the centroids are binned into when looking for a split
+    pub n_bins: usize,
+    /// Cost of visiting an interior node, relative to `intersection_cost`
+    pub traversal_cost: f32,
+    /// Cost of intersecting a single primitive
+    pub intersection_cost: f32,
+}
+
+impl Default for BvhParams {
+    fn default() -> Self {
+        BvhParams {
+            max_leaf_size: 4,
+            n_bins: 12,
+            traversal_cost: 1.,
+            intersection_cost: 1.,
+        }
+    }
+}
+
+/// Node of the flattened tree. The nodes are stored depth first, so the
+/// left child of an interior node always directly follows its parent and
+/// only the index of the right child needs to be kept.
+#[derive(Debug, Clone, Copy)]
+struct LinearNode {
+    bbox: Aabb,
+    // First object of a leaf, or index of the right child of an interior node
+    offset: usize,
+    // Number of objects in a leaf, 0 for interior nodes
+    count: usize,
+    axis: usize,
+}
+
+/// Bounding volume hierarchy over a set of primitives, built with the
+/// surface area heuristic on binned centroids.
+pub struct Bvh<'a> {
+    objects: Vec<Box<dyn Primitive + 'a>>,
+    nodes: Vec<LinearNode>,
+}
+
+struct BuildItem {
+    index: usize,
+    bbox: Aabb,
+    centroid: nalgebra::Vector3<f32>,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bbox: Aabb,
+    count: usize,
+}
+
+const STACK_SIZE: usize = 64;
+// Below this depth nodes are split at the median, which keeps the tree
+// shallow enough for the traversal stack whatever the heuristic decides
+const MAX_SAH_DEPTH: usize = 24;
+
+impl<'a> Bvh<'a> {
+    pub fn new(objects: Vec<Box<dyn Primitive + 'a>>) -> Bvh<'a> {
+        Bvh::with_params(objects, BvhParams::default())
+    }
+
+    pub fn with_params(objects: Vec<Box<dyn Primitive + 'a>>, params: BvhParams) -> Bvh<'a> {
+        let mut items: Vec<BuildItem> = objects.iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bbox = object.bounding_box();
+                BuildItem { index, bbox, centroid: bbox.centroid() }
+            })
+            .collect();
+
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            build(&mut items, 0, 0, &params, &mut nodes);
+        }
+
+        // Reorder the objects so that each leaf points to a contiguous range
+        let mut slots: Vec<Option<Box<dyn Primitive + 'a>>> = objects.into_iter().map(Some).collect();
+        let objects = items.iter()
+            .map(|item| slots[item.index].take().unwrap())
+            .collect();
+
+        Bvh { objects, nodes }
+    }
+}
+
+fn build(items: &mut [BuildItem], first: usize, depth: usize, params: &BvhParams, nodes: &mut Vec<LinearNode>) -> usize {
+    let bbox = items.iter().fold(Aabb::empty(), |acc, item| acc.surrounding(&item.bbox));
+    let centroid_bounds = items.iter().fold(Aabb::empty(), |acc, item| acc.enclose(&item.centroid));
+    let axis = centroid_bounds.longest_axis();
+    let n = items.len();
+
+    let node_index = nodes.len();
+    nodes.push(LinearNode { bbox, offset: first, count: n, axis });
+
+    if n == 1 {
+        return node_index
+    }
+
+    let min = centroid_bounds.min[axis];
+    let extent = centroid_bounds.max[axis] - min;
+    let mid = if depth >= MAX_SAH_DEPTH {
+        0
+    } else if extent <= 0. {
+        // All centroids coincide, no split can separate them
+        if n <= params.max_leaf_size {
+            return node_index
+        }
+        n / 2
+    } else {
+        let n_bins = params.n_bins.max(2);
+        let bin_of = |item: &BuildItem| {
+            let b = ((item.centroid[axis] - min) / extent * n_bins as f32) as usize;
+            b.min(n_bins - 1)
+        };
+
+        let mut bins = vec![Bin { bbox: Aabb::empty(), count: 0 }; n_bins];
+        for item in items.iter() {
+            let bin = &mut bins[bin_of(item)];
+            bin.bbox = bin.bbox.surrounding(&item.bbox);
+            bin.count += 1;
+        }
+
+        // Sweep from the right to get the area and count of every suffix,
+        // then from the left to evaluate each of the n_bins - 1 splits.
+        let mut right_area = vec![0.; n_bins];
+        let mut right_count = vec![0; n_bins];
+        let mut acc = Bin { bbox: Aabb::empty(), count: 0 };
+        for i in (1..n_bins).rev() {
+            acc.bbox = acc.bbox.surrounding(&bins[i].bbox);
+            acc.count += bins[i].count;
+            right_area[i] = acc.bbox.surface_area();
+            right_count[i] = acc.count;
+        }
+
+        let mut best_cost = f32::MAX;
+        let mut best_split = 0;
+        let mut acc = Bin { bbox: Aabb::empty(), count: 0 };
+        for i in 0..n_bins - 1 {
+            acc.bbox = acc.bbox.surrounding(&bins[i].bbox);
+            acc.count += bins[i].count;
+            let cost = acc.count as f32 * acc.bbox.surface_area()
+                + right_count[i + 1] as f32 * right_area[i + 1];
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = i;
+            }
+        }
+
+        let split_cost = params.traversal_cost
+            + params.intersection_cost * best_cost / bbox.surface_area().max(f32::MIN_POSITIVE);
+        let leaf_cost = params.intersection_cost * n as f32;
+        if n <= params.max_leaf_size && leaf_cost <= split_cost {
+            return node_index
+        }
+
+        partition(items, |item| bin_of(item) <= best_split)
+    };
+
+    // A degenerate partition can happen with huge objects, fall back to halves
+    let mid = if mid == 0 || mid == n {
+        items.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+        n / 2
+    } else {
+        mid
+    };
+
+    let (left, right) = items.split_at_mut(mid);
+    build(left, first, depth + 1, params, nodes);
+    let right_index = build(right, first + mid, depth + 1, params, nodes);
+    nodes[node_index].offset = right_index;
+    nodes[node_index].count = 0;
+    node_index
+}
+
+// In-place partition, returns the number of items satisfying the predicate
+fn partition<F: Fn(&BuildItem) -> bool>(items: &mut [BuildItem], predicate: F) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+impl Primitive for Bvh<'_> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None
+        }
+
+        let negative_direction = [ray.direction.x < 0., ray.direction.y < 0., ray.direction.z < 0.];
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let index = stack[stack_size];
+            let node = &self.nodes[index];
+            if !node.bbox.hit(ray, t_min, closest_so_far) {
+                continue
+            }
+
+            if node.count > 0 {
+                for object in &self.objects[node.offset..node.offset + node.count] {
+                    if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                        closest_so_far = hit.t;
+                        hit_record = Some(hit);
+                    }
+                }
+            } else {
+                // Push the far child first so that the near one is visited next
+                let left = index + 1;
+                let right = node.offset;
+                if negative_direction[node.axis] {
+                    stack[stack_size] = left;
+                    stack[stack_size + 1] = right;
+                } else {
+                    stack[stack_size] = right;
+                    stack[stack_size + 1] = left;
+                }
+                stack_size += 2;
+            }
+        }
+        hit_record
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.nodes.first() {
+            None => Aabb::new(ORIGIN, ORIGIN),
+            Some(root) => root.bbox,
+        }
+    }
+}
+
+#[test]
+fn test_bvh_closest_hit() {
+    let material = Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mut objects = Vec::<Box<dyn Primitive>>::new();
+    for i in 0..100 {
+        objects.push(Box::new(Sphere::new(Vector3::new(0., 0., i as f32 * 3.), 1., material.clone())));
+    }
+    let bvh = Bvh::new(objects);
+
+    let ray = Ray::new(Vector3::new(0., 0., 1000.), Vector3::new(0., 0., -1.));
+    let hit = bvh.hit(&ray, EPSILON, INF).unwrap();
+    assert!((hit.position.z - 298.).abs() < 1e-3);
+
+    let ray = Ray::new(Vector3::new(0., 0., -1000.), Vector3::new(0., 0., 1.));
+    let hit = bvh.hit(&ray, EPSILON, INF).unwrap();
+    assert!((hit.position.z + 1.).abs() < 1e-3);
+
+    let miss = Ray::new(Vector3::new(5., 0., 100.), Vector3::new(0., 0., -1.));
+    assert!(bvh.hit(&miss, EPSILON, INF).is_none());
+}
+
+#[test]
+fn test_bvh_coincident_centroids() {
+    let material = Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mut objects = Vec::<Box<dyn Primitive>>::new();
+    for i in 0..20 {
+        objects.push(Box::new(Sphere::new(ORIGIN, 1. + i as f32, material.clone())));
+    }
+    let bvh = Bvh::new(objects);
+
+    let ray = Ray::new(Vector3::new(0., 0., -100.), Vector3::new(0., 0., 1.));
+    let hit = bvh.hit(&ray, EPSILON, INF).unwrap();
+    assert!((hit.position.z + 20.).abs() < 1e-3);
+}
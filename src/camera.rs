@@ -1,34 +1,109 @@
-use crate::{ray::Ray};
+use std::f32::consts::PI;
+use crate::{ray::Ray, random};
 use nalgebra::Vector3;
+use rand::Rng;
 
 #[cfg(test)]
 use assert_approx_eq::assert_approx_eq;
 
-#[derive(Debug)]
-pub struct Camera {
+/// Shape of the lens opening, which gives its shape to out of focus highlights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApertureShape {
+    Disk,
+    /// Regular polygon with `blades` sides, rotated by `rotation` degrees.
+    Polygon { blades: u32, rotation: f32 },
+}
+
+impl ApertureShape {
+    /// Uniform point of the aperture of radius 1, in the lens plane.
+    pub fn sample(&self) -> (f32, f32) {
+        let mut rng = random::rng();
+        match *self {
+            ApertureShape::Disk => loop {
+                let (x, y) = (rng.gen_range(-1f32..1.), rng.gen_range(-1f32..1.));
+                if x * x + y * y < 1. {
+                    return (x, y)
+                }
+            },
+            ApertureShape::Polygon { blades, rotation } => {
+                // The triangles between the center and each side have the
+                // same area: pick one, then a uniform point inside it
+                let blades = blades.max(3);
+                let side = rng.gen_range(0..blades) as f32;
+                let step = 2. * PI / blades as f32;
+                let angle0 = rotation.to_radians() + side * step;
+                let (a, b) = ((angle0.cos(), angle0.sin()), ((angle0 + step).cos(), (angle0 + step).sin()));
+                let (r1, r2): (f32, f32) = (rng.gen(), rng.gen());
+                let s = r1.sqrt();
+                let (wa, wb) = (s * (1. - r2), s * r2);
+                (wa * a.0 + wb * b.0, wa * a.1 + wb * b.1)
+            }
+        }
+    }
+}
+
+/// Projection of the image onto the rays leaving the camera.
+pub trait Camera: Send + Sync {
+    /// Ray through the point (`s`, `t`) of the image, both in [0, 1] from
+    /// the bottom left corner. Points outside of the field of view, such as
+    /// the corners of a circular fisheye, have none.
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray>;
+}
+
+/// Pinhole or thin lens camera.
+#[derive(Debug, Clone)]
+pub struct PerspectiveCamera {
     pub origin: Vector3<f32>,
     pub horizontal: Vector3<f32>,
     pub vertical: Vector3<f32>,
-    pub focal_length: f32,
     pub lower_left_corner: Vector3<f32>,
-    // look_from: Vector3<f32>,
-    // look_at: Vector3<f32>,
-    // vup: Vector3<f32>, // Vertical up, define the rotation
-    // vfov: f32, // Vertical FOV
-    // aspect_ratio: f32,
+    // Basis of the lens plane
+    pub u: Vector3<f32>,
+    pub v: Vector3<f32>,
+    pub lens_radius: f32,
+    pub aperture_shape: ApertureShape,
+    // Rays are spread uniformly over the time the shutter is open
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel rays, over a view `height` in world units.
+    Orthographic { height: f32 },
+    /// Equidistant circular fisheye, `vfov` is the angle across the circle.
+    Fisheye,
+    /// Full longitude and latitude panorama.
+    Equirectangular,
 }
 
 #[derive(Debug)]
 pub struct CameraParams {
+    pub projection: Projection,
     pub look_from: Vector3<f32>,
     pub look_at: Vector3<f32>,
     pub vup: Vector3<f32>, // Vertical up, define the rotation
     pub vfov: f32, // Vertical FOV
     pub aspect_ratio: f32,
+    pub aperture: f32, // Diameter of the lens, 0 for a pinhole
+    pub focus_distance: f32, // Distance to the plane in focus
+    pub aperture_shape: ApertureShape,
+    pub shutter_open: f32,
+    pub shutter_close: f32,
 }
 
-impl Camera {
-    pub fn new(look_from: Vector3<f32>, look_at: Vector3<f32>, vup: Vector3<f32>, vfov: f32, aspect_ratio: f32) -> Camera {
+impl PerspectiveCamera {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        look_from: Vector3<f32>,
+        look_at: Vector3<f32>,
+        vup: Vector3<f32>,
+        vfov: f32,
+        aspect_ratio: f32,
+        aperture: f32,
+        focus_distance: f32,
+        ) -> PerspectiveCamera {
         let theta = vfov.to_radians();
         let half_height = (theta / 2.).tan();
         let half_width = aspect_ratio * half_height;
@@ -38,41 +113,212 @@ impl Camera {
         let u = vup.cross(&w).normalize();
         let v = w.cross(&u);
 
+        // The image plane is the plane in focus
         let origin = look_from;
-        let lower_left_corner = origin - half_width * u - half_height * v - w;
-        let horizontal = u * 2. * half_width;
-        let vertical = v * 2. * half_height;
+        let lower_left_corner = origin - focus_distance * (half_width * u + half_height * v + w);
+        let horizontal = u * 2. * half_width * focus_distance;
+        let vertical = v * 2. * half_height * focus_distance;
 
-        Camera { 
+        PerspectiveCamera { 
             origin,
             horizontal,
             vertical,
-            focal_length: (look_from - look_at).norm(), 
             lower_left_corner,
-            // look_from,
-            // look_at,
-            // vup,
-            // vfov,
-            // aspect_ratio
+            u,
+            v,
+            lens_radius: aperture / 2.,
+            aperture_shape: ApertureShape::Disk,
+            shutter_open: 0.,
+            shutter_close: 0.,
+        }
+    }
+
+    pub fn from_params(params: &CameraParams) -> PerspectiveCamera {
+        PerspectiveCamera::new(
+            params.look_from,
+            params.look_at,
+            params.vup,
+            params.vfov,
+            params.aspect_ratio,
+            params.aperture,
+            params.focus_distance)
+            .with_aperture_shape(params.aperture_shape)
+            .with_shutter(params.shutter_open, params.shutter_close)
+    }
+
+    pub fn with_aperture_shape(mut self, aperture_shape: ApertureShape) -> PerspectiveCamera {
+        self.aperture_shape = aperture_shape;
+        self
+    }
+
+    pub fn with_shutter(mut self, shutter_open: f32, shutter_close: f32) -> PerspectiveCamera {
+        self.shutter_open = shutter_open;
+        self.shutter_close = shutter_close;
+        self
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        // Rays start from a random point of the lens, and all cross the
+        // plane in focus at the same point
+        let offset = if self.lens_radius > 0. {
+            let (x, y) = self.aperture_shape.sample();
+            (self.u * x + self.v * y) * self.lens_radius
+        } else {
+            Vector3::zeros()
+        };
+        Some(Ray::with_time(
+            self.origin + offset,
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            shutter_time(self.shutter_open, self.shutter_close)
+            ))
+    }
+}
+
+// Time of a ray, uniform over the opening of the shutter
+fn shutter_time(shutter_open: f32, shutter_close: f32) -> f32 {
+    if shutter_close > shutter_open {
+        random::rng().gen_range(shutter_open..shutter_close)
+    } else {
+        shutter_open
+    }
+}
+
+/// Position and orientation shared by the cameras without a lens: `w`
+/// points backwards, away from where the camera looks.
+#[derive(Debug, Clone)]
+pub struct CameraFrame {
+    pub origin: Vector3<f32>,
+    pub u: Vector3<f32>,
+    pub v: Vector3<f32>,
+    pub w: Vector3<f32>,
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+impl CameraFrame {
+    pub fn new(look_from: Vector3<f32>, look_at: Vector3<f32>, vup: Vector3<f32>) -> CameraFrame {
+        let w = (look_from - look_at).normalize();
+        let u = vup.cross(&w).normalize();
+        let v = w.cross(&u);
+        CameraFrame { origin: look_from, u, v, w, shutter_open: 0., shutter_close: 0. }
+    }
+
+    pub fn with_shutter(mut self, shutter_open: f32, shutter_close: f32) -> CameraFrame {
+        self.shutter_open = shutter_open;
+        self.shutter_close = shutter_close;
+        self
+    }
+
+    fn ray(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> Ray {
+        Ray::with_time(origin, direction, shutter_time(self.shutter_open, self.shutter_close))
+    }
+}
+
+/// Parallel rays along the viewing direction, starting from the plane of
+/// `look_from`. Useful for architectural elevations and plans.
+#[derive(Debug, Clone)]
+pub struct OrthographicCamera {
+    pub frame: CameraFrame,
+    pub height: f32,
+    pub aspect_ratio: f32,
+}
+
+impl OrthographicCamera {
+    pub fn new(frame: CameraFrame, height: f32, aspect_ratio: f32) -> OrthographicCamera {
+        OrthographicCamera { frame, height, aspect_ratio }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let frame = &self.frame;
+        let x = (s - 0.5) * self.height * self.aspect_ratio;
+        let y = (t - 0.5) * self.height;
+        Some(frame.ray(frame.origin + x * frame.u + y * frame.v, -frame.w))
+    }
+}
+
+/// Equidistant fisheye: the angle to the viewing direction grows linearly
+/// with the distance to the center of the image, up to `fov / 2` on the
+/// circle inscribed in the image height.
+#[derive(Debug, Clone)]
+pub struct FisheyeCamera {
+    pub frame: CameraFrame,
+    /// Field of view across the image circle, in degrees. May exceed 180.
+    pub fov: f32,
+    pub aspect_ratio: f32,
+}
+
+impl FisheyeCamera {
+    pub fn new(frame: CameraFrame, fov: f32, aspect_ratio: f32) -> FisheyeCamera {
+        FisheyeCamera { frame, fov, aspect_ratio }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let frame = &self.frame;
+        let x = (2. * s - 1.) * self.aspect_ratio;
+        let y = 2. * t - 1.;
+        let r = (x * x + y * y).sqrt();
+        if r > 1. {
+            return None
         }
+        let theta = r * self.fov.to_radians() / 2.;
+        let phi = y.atan2(x);
+        let direction = theta.sin() * (phi.cos() * frame.u + phi.sin() * frame.v) - theta.cos() * frame.w;
+        Some(frame.ray(frame.origin, direction))
+    }
+}
+
+/// Latitude and longitude panorama of the whole sphere of directions, with
+/// the viewing direction at the center. Meant for 2:1 images.
+#[derive(Debug, Clone)]
+pub struct EquirectangularCamera {
+    pub frame: CameraFrame,
+}
+
+impl EquirectangularCamera {
+    pub fn new(frame: CameraFrame) -> EquirectangularCamera {
+        EquirectangularCamera { frame }
     }
+}
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        Ray::new(
-            self.origin,
-            self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin
-            )
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let frame = &self.frame;
+        let longitude = (s - 0.5) * 2. * PI;
+        let latitude = (t - 0.5) * PI;
+        let direction = latitude.cos() * (longitude.sin() * frame.u - longitude.cos() * frame.w)
+            + latitude.sin() * frame.v;
+        Some(frame.ray(frame.origin, direction))
+    }
+}
+
+/// Camera of the projection chosen in `params`.
+pub fn camera_from_params(params: &CameraParams) -> Box<dyn Camera> {
+    let frame = || CameraFrame::new(params.look_from, params.look_at, params.vup)
+        .with_shutter(params.shutter_open, params.shutter_close);
+    match params.projection {
+        Projection::Perspective => Box::new(PerspectiveCamera::from_params(params)),
+        Projection::Orthographic { height } => Box::new(OrthographicCamera::new(frame(), height, params.aspect_ratio)),
+        Projection::Fisheye => Box::new(FisheyeCamera::new(frame(), params.vfov, params.aspect_ratio)),
+        Projection::Equirectangular => Box::new(EquirectangularCamera::new(frame())),
     }
 }
 
 #[test]
 fn test_camera() {
-    let camera = Camera::new(
+    let camera = PerspectiveCamera::new(
         Vector3::new(0.0, 0.0, 0.0),
         Vector3::new(0.0, 0.0, -1.0),
         Vector3::new(0.0, 1.0, 0.0),
         90.0,
         (800.0 / 600.0) as f32,
+        0.0,
+        1.0,
     );
     assert_eq!(camera.origin.x, 0.0);
     assert_eq!(camera.origin.y, 0.0);
@@ -85,14 +331,16 @@ fn test_camera() {
 
 #[test]
 fn test_camera_get_ray() {
-    let camera = Camera::new(
+    let camera = PerspectiveCamera::new(
         Vector3::new(-4.0, 4.0, 1.0),
         Vector3::new(0.0, 0.0, -1.0),
         Vector3::new(0.0, 1.0, 0.0),
         160.0,
         (800 / 600) as f32,
+        0.0,
+        1.0,
     );
-    let ray = camera.get_ray(0.5, 0.5);
+    let ray = camera.get_ray(0.5, 0.5).unwrap();
     assert_eq!(ray.origin.x, -4.0);
     assert_eq!(ray.origin.y, 4.0);
     assert_eq!(ray.origin.z, 1.0);
@@ -102,3 +350,51 @@ fn test_camera_get_ray() {
     assert_approx_eq!(ray.direction.z, -(1.0 / 3.0));
 }
 
+
+#[test]
+fn test_camera_aperture() {
+    // Every ray goes through the point in focus, from the lens
+    let camera = PerspectiveCamera::new(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        1.0,
+        0.5,
+        4.0,
+    );
+    for shape in [ApertureShape::Disk, ApertureShape::Polygon { blades: 6, rotation: 15.0 }] {
+        let camera = camera.clone().with_aperture_shape(shape);
+        for _ in 0..100 {
+            let ray = camera.get_ray(0.5, 0.5).unwrap();
+            assert!(ray.origin.norm() <= 0.25 + 1e-6 && ray.origin.z == 0.0);
+            let focus = ray.at(1.0);
+            assert_approx_eq!(focus.x, 0.0);
+            assert_approx_eq!(focus.y, 0.0);
+            assert_approx_eq!(focus.z, -4.0);
+        }
+    }
+}
+
+#[test]
+fn test_camera_projections() {
+    let frame = CameraFrame::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0));
+    let close = |a: Vector3<f32>, b: Vector3<f32>| (a - b).norm() < 1e-5;
+    let (x, y, z) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+
+    let orthographic = OrthographicCamera::new(frame.clone(), 2.0, 2.0);
+    let ray = orthographic.get_ray(0.0, 0.0).unwrap();
+    assert!(close(ray.origin, Vector3::new(-2.0, -1.0, 0.0)) && close(ray.direction, -z));
+
+    let fisheye = FisheyeCamera::new(frame.clone(), 180.0, 1.0);
+    assert!(close(fisheye.get_ray(0.5, 0.5).unwrap().direction, -z));
+    assert!(close(fisheye.get_ray(1.0, 0.5).unwrap().direction.normalize(), x));
+    assert!(close(fisheye.get_ray(0.5, 0.75).unwrap().direction.normalize(), (y - z).normalize()));
+    assert!(fisheye.get_ray(0.0, 0.0).is_none());
+
+    let panorama = EquirectangularCamera::new(frame);
+    assert!(close(panorama.get_ray(0.5, 0.5).unwrap().direction, -z));
+    assert!(close(panorama.get_ray(0.75, 0.5).unwrap().direction, x));
+    assert!(close(panorama.get_ray(0.0, 0.5).unwrap().direction, z));
+    assert!(close(panorama.get_ray(0.3, 1.0).unwrap().direction, y));
+}
@@ -1,7 +1,9 @@
 use std::ops::{Add, Sub, Mul};
 use rand::prelude::*;
 
-#[derive(Clone, Copy, Debug)]
+use crate::random;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -14,15 +16,54 @@ impl Color {
     }
 
     pub fn scale(&self, s: f32) -> Self {
-        Self { r: s * &self.r, g: s * &self.g, b: s * self.b}
+        Self { r: s * self.r, g: s * self.g, b: s * self.b}
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.r == 0. && self.g == 0. && self.b == 0.
+    }
+
+    /// Relative luminance with the Rec. 709 weights.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Decode an sRGB encoded color to linear values.
+    pub fn srgb_to_linear(&self) -> Self {
+        Self { r: srgb_to_linear(self.r), g: srgb_to_linear(self.g), b: srgb_to_linear(self.b) }
+    }
+
+    /// Encode linear values with the sRGB transfer function.
+    pub fn linear_to_srgb(&self) -> Self {
+        Self { r: linear_to_srgb(self.r), g: linear_to_srgb(self.g), b: linear_to_srgb(self.b) }
+    }
+
+    pub fn map<F: Fn(f32) -> f32>(&self, f: F) -> Self {
+        Self { r: f(self.r), g: f(self.g), b: f(self.b) }
     }
 
     pub fn random() -> Self {
-        let mut rng = thread_rng();
+        let mut rng = random::rng();
         Self{ r: rng.gen(), g: rng.gen(), b: rng.gen() }
     }
 }
 
+pub fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
 impl Add for Color {
     type Output = Self;
 
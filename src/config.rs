@@ -1,12 +1,23 @@
 use crate::camera::Camera;
+use crate::background::Background;
 use crate::primitives::Primitive;
+use crate::tonemap::DisplayTransform;
 
 pub struct Config {
     pub width: usize,
     pub height: usize,
     pub samples_per_pixel: usize,
-    pub camera: Camera,
+    pub camera: Box<dyn Camera>,
     pub objects: Vec<Box<dyn Primitive>>,
     pub depth: usize,
+    /// Radiance of the rays leaving the scene.
+    pub background: Background,
+    /// Number of worker threads, all the available cores if `None`.
+    pub threads: Option<usize>,
+    /// Seed of the random generators. Rows are rendered from generators
+    /// seeded with it, so that the image does not depend on the scheduling.
+    pub seed: Option<u64>,
+    /// Conversion to display values, for formats that are not high dynamic range.
+    pub display: DisplayTransform,
 }
 
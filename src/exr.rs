@@ -0,0 +1,259 @@
+use std::io::{self, Write};
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{color::Color, image::Image, image_io::ImageWriter};
+
+#[cfg(test)]
+use flate2::read::ZlibDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExrCompression {
+    None,
+    /// Deflate over blocks of 16 scanlines
+    Zip,
+}
+
+/// Single part scanline OpenEXR file with R, G and B channels.
+#[derive(Debug, Clone, Copy)]
+pub struct ExrWriter {
+    pub pixel_type: ExrPixelType,
+    pub compression: ExrCompression,
+}
+
+impl ExrWriter {
+    fn lines_per_block(&self) -> usize {
+        match self.compression {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => 16,
+        }
+    }
+
+    fn header(&self, width: usize, height: usize) -> Vec<u8> {
+        let mut header = Vec::new();
+        // Magic number, then version 2 for a single part scanline file
+        header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let mut channels = Vec::new();
+        let pixel_type: i32 = match self.pixel_type {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        };
+        // Channels are sorted by name
+        for name in ["B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&pixel_type.to_le_bytes());
+            // Not perceptually linear, and 3 reserved bytes
+            channels.extend_from_slice(&[0, 0, 0, 0]);
+            // No subsampling
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        attribute(&mut header, "channels", "chlist", &channels);
+
+        let compression = match self.compression {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        };
+        attribute(&mut header, "compression", "compression", &[compression]);
+
+        let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+        // Increasing Y
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+        header
+    }
+
+    // Uncompressed content of the block of lines: each line stores all the
+    // blue values, then the green ones and the red ones.
+    fn raw_block(&self, width: usize, lines: &[Color]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for line in lines.chunks_exact(width) {
+            for channel in [|c: &Color| c.b, |c: &Color| c.g, |c: &Color| c.r] {
+                for color in line {
+                    match self.pixel_type {
+                        ExrPixelType::Half => data.extend_from_slice(&f32_to_f16(channel(color)).to_le_bytes()),
+                        ExrPixelType::Float => data.extend_from_slice(&channel(color).to_le_bytes()),
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    fn block(&self, width: usize, lines: &[Color]) -> io::Result<Vec<u8>> {
+        let raw = self.raw_block(width, lines);
+        match self.compression {
+            ExrCompression::None => Ok(raw),
+            ExrCompression::Zip => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&zip_predictor(&raw))?;
+                let compressed = encoder.finish()?;
+                // Blocks that do not shrink are stored as they are
+                Ok(if compressed.len() < raw.len() { compressed } else { raw })
+            }
+        }
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// Reorder the bytes before deflating them, as done by OpenEXR: the even
+// bytes then the odd ones (low then high bytes of halfs), stored as
+// differences with the previous one.
+fn zip_predictor(raw: &[u8]) -> Vec<u8> {
+    let mut data: Vec<u8> = raw.iter().step_by(2).chain(raw.iter().skip(1).step_by(2)).copied().collect();
+    let Some(&first) = data.first() else {
+        return data
+    };
+    let mut previous = first;
+    for byte in data.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+    data
+}
+
+/// Convert to a half precision float, rounding to the nearest. Values too
+/// large become infinite.
+pub fn f32_to_f16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity, or NaN kept as a NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 }
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00
+    }
+
+    let (value, shift) = if exponent <= 0 {
+        // Subnormal half, the implicit leading bit becomes explicit
+        if exponent < -10 {
+            return sign
+        }
+        (mantissa | 0x80_0000, (14 - exponent) as u32)
+    } else {
+        (((exponent as u32) << 23) | mantissa, 13)
+    };
+    let mut half = value >> shift;
+    let remainder = value & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    // Ties to even. Carrying into the exponent is correct, up to infinity.
+    if remainder > halfway || (remainder == halfway && half & 1 == 1) {
+        half += 1;
+    }
+    sign | half as u16
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, output: &mut dyn Write, image: &Image) -> io::Result<()> {
+        let (width, height) = (image.width, image.height);
+        // The data window of an EXR file holds at least one pixel
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot write an empty image"))
+        }
+        let header = self.header(width, height);
+        let lines_per_block = self.lines_per_block();
+        let blocks = image.pixels
+            .chunks(width * lines_per_block)
+            .map(|lines| self.block(width, lines))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Offsets of the blocks from the start of the file, then the blocks
+        // preceded by their first line and their size
+        let mut offset = (header.len() + 8 * blocks.len()) as u64;
+        let mut output = io::BufWriter::new(output);
+        output.write_all(&header)?;
+        for block in &blocks {
+            output.write_all(&offset.to_le_bytes())?;
+            offset += 8 + block.len() as u64;
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            output.write_all(&((i * lines_per_block) as i32).to_le_bytes())?;
+            output.write_all(&(block.len() as i32).to_le_bytes())?;
+            output.write_all(block)?;
+        }
+        output.flush()
+    }
+
+    fn is_hdr(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_f32_to_f16() {
+    assert_eq!(f32_to_f16(0.), 0);
+    assert_eq!(f32_to_f16(1.), 0x3c00);
+    assert_eq!(f32_to_f16(-2.), 0xc000);
+    assert_eq!(f32_to_f16(0.333333), 0x3555);
+    assert_eq!(f32_to_f16(65504.), 0x7bff);
+    assert_eq!(f32_to_f16(1e6), 0x7c00);
+    // Smallest subnormal, and a value rounding down to zero
+    assert_eq!(f32_to_f16(5.960464e-8), 0x0001);
+    assert_eq!(f32_to_f16(2e-8), 0);
+    assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+}
+
+#[test]
+fn test_exr_zip_blocks() {
+    let (width, height) = (5, 20);
+    let image = Image::from_pixels(width, height, (0..width * height)
+        .map(|i| Color::new(i as f32 / 10., 1., 100.))
+        .collect());
+    let raw = ExrWriter { pixel_type: ExrPixelType::Half, compression: ExrCompression::None };
+    let zip = ExrWriter { compression: ExrCompression::Zip, ..raw };
+
+    let mut bytes = Vec::new();
+    raw.write(&mut bytes, &image).unwrap();
+    let header = raw.header(width, height);
+    // 20 lines of 5 pixels with 3 half channels, each with its offset, line and size
+    assert_eq!(bytes.len(), header.len() + 20 * (8 + 8 + 5 * 3 * 2));
+    assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+    // Undo the compression of the first block, of 16 lines
+    let mut bytes = Vec::new();
+    zip.write(&mut bytes, &image).unwrap();
+    let header = zip.header(width, height);
+    let start = header.len() + 2 * 8;
+    let size = i32::from_le_bytes(bytes[start + 4..start + 8].try_into().unwrap()) as usize;
+    let mut data = Vec::new();
+    io::Read::read_to_end(&mut ZlibDecoder::new(&bytes[start + 8..start + 8 + size]), &mut data).unwrap();
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+    let (even, odd) = data.split_at(data.len().div_ceil(2));
+    let restored: Vec<u8> = even.iter().zip(odd).flat_map(|(&a, &b)| [a, b]).collect();
+    assert_eq!(restored, raw.raw_block(width, &image.pixels[..16 * width]));
+
+    assert!(zip.write(&mut Vec::new(), &Image::from_pixels(0, 0, Vec::new())).is_err());
+    assert!(zip_predictor(&[]).is_empty());
+}
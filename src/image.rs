@@ -0,0 +1,38 @@
+use crate::color::*;
+
+/// Framebuffer of linear RGB pixels, stored row by row from the top.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    /// Black image.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image { width, height, pixels: vec![BLACK; width * height] }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+        assert_eq!(pixels.len(), width * height, "image size does not match its dimensions");
+        Image { width, height, pixels }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, Color> {
+        self.pixels.chunks_exact(self.width.max(1))
+    }
+
+    /// New image with `f` applied to every pixel.
+    pub fn map<F: Fn(&Color) -> Color>(&self, f: F) -> Image {
+        Image { width: self.width, height: self.height, pixels: self.pixels.iter().map(f).collect() }
+    }
+}
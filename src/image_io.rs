@@ -0,0 +1,557 @@
+use std::{fmt, fs, io::{self, Write}, path::{Path, PathBuf}};
+
+use crate::{color::*, image::Image, exr::*};
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(PathBuf, std::io::Error),
+    Format(PathBuf, String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ImageError::Format(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Decoded image, row by row from the top. The values are the ones stored in
+/// the file: for low dynamic range formats, mapped to [0, 1] but not
+/// linearized, and `linear` is false.
+pub struct RawImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    /// Whether the values are linear radiance, as in high dynamic range files.
+    pub linear: bool,
+}
+
+/// Read a PPM (P3 or P6), PNG, PFM or Radiance HDR file, picked from the
+/// extension.
+pub fn read_image<P: AsRef<Path>>(path: P) -> Result<RawImage, ImageError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|e| ImageError::Io(path.to_path_buf(), e))?;
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    let result = match extension.as_deref() {
+        Some("ppm") => decode_ppm(&bytes),
+        Some("png") => decode_png(&bytes),
+        Some("pfm") => decode_pfm(&bytes),
+        Some("hdr") => decode_hdr(&bytes),
+        _ => Err("unsupported image format".to_string()),
+    };
+    result.map_err(|message| ImageError::Format(path.to_path_buf(), message))
+}
+
+pub fn decode_ppm(bytes: &[u8]) -> Result<RawImage, String> {
+    // Header: magic, width, height and maximum value, separated by
+    // whitespace and possibly comments
+    let mut position = 0;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        while position < bytes.len() && (bytes[position].is_ascii_whitespace() || bytes[position] == b'#') {
+            if bytes[position] == b'#' {
+                while position < bytes.len() && bytes[position] != b'\n' {
+                    position += 1;
+                }
+            } else {
+                position += 1;
+            }
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err("truncated PPM header".to_string())
+        }
+        header.push(String::from_utf8_lossy(&bytes[start..position]).to_string());
+    }
+
+    let parse = |token: &str| token.parse::<usize>().map_err(|_| format!("invalid PPM header value `{}`", token));
+    let width = parse(&header[1])?;
+    let height = parse(&header[2])?;
+    let max_value = parse(&header[3])?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(format!("invalid PPM maximum value {}", max_value))
+    }
+    // Every value takes at least one byte, or two past 255 in binary files
+    let bytes_per_value = if header[0] == "P6" && max_value >= 256 { 2 } else { 1 };
+    let n_values = width.checked_mul(height)
+        .and_then(|n| n.checked_mul(3))
+        .filter(|n| n.checked_mul(bytes_per_value).is_some_and(|size| size <= bytes.len() - position))
+        .ok_or_else(|| format!("invalid PPM size {}x{}", width, height))?;
+
+    let values: Vec<usize> = match header[0].as_str() {
+        "P3" => {
+            let text = String::from_utf8_lossy(&bytes[position..]);
+            text.split_whitespace()
+                .take(n_values)
+                .map(parse)
+                .collect::<Result<_, _>>()?
+        }
+        "P6" => {
+            // A single whitespace separates the header from the binary data
+            let data = &bytes[(position + 1).min(bytes.len())..];
+            if max_value < 256 {
+                data.iter().take(n_values).map(|&b| b as usize).collect()
+            } else {
+                data.chunks_exact(2).take(n_values).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).collect()
+            }
+        }
+        magic => return Err(format!("unsupported PPM type `{}`", magic)),
+    };
+    if values.len() < n_values {
+        return Err(format!("expected {} values, found {}", n_values, values.len()))
+    }
+
+    let max_value = max_value as f32;
+    let pixels = values.chunks_exact(3)
+        .map(|c| Color::new(c[0] as f32 / max_value, c[1] as f32 / max_value, c[2] as f32 / max_value))
+        .collect();
+    Ok(RawImage { width, height, pixels, linear: false })
+}
+
+pub fn decode_png(bytes: &[u8]) -> Result<RawImage, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Expand palettes and low bit depths, keep 16 bit samples as they are
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+
+    let channels = info.color_type.samples();
+    let sixteen_bits = info.bit_depth == png::BitDepth::Sixteen;
+    let max_value = if sixteen_bits { 65535. } else { 255. };
+    let sample = |row: &[u8], i: usize| if sixteen_bits {
+        u16::from_be_bytes([row[2 * i], row[2 * i + 1]]) as f32 / max_value
+    } else {
+        row[i] as f32 / max_value
+    };
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = &buffer[y * info.line_size..(y + 1) * info.line_size];
+        for x in 0..width {
+            let i = x * channels;
+            // Grey images only have one (or two with alpha) channels, alpha is dropped
+            let color = if channels < 3 {
+                let v = sample(row, i);
+                Color::new(v, v, v)
+            } else {
+                Color::new(sample(row, i), sample(row, i + 1), sample(row, i + 2))
+            };
+            pixels.push(color);
+        }
+    }
+    Ok(RawImage { width, height, pixels, linear: false })
+}
+
+// Next line of a text header, without its line feed
+fn header_line<'a>(bytes: &'a [u8], position: &mut usize) -> Result<&'a str, String> {
+    let start = *position;
+    let end = bytes[start..].iter()
+        .position(|&b| b == b'\n')
+        .map(|n| start + n)
+        .ok_or("truncated header")?;
+    *position = end + 1;
+    std::str::from_utf8(&bytes[start..end]).map_err(|_| "invalid header".to_string())
+}
+
+/// Decode a portable float map, in color (PF) or grey (Pf).
+pub fn decode_pfm(bytes: &[u8]) -> Result<RawImage, String> {
+    let mut position = 0;
+    let channels = match header_line(bytes, &mut position)?.trim() {
+        "PF" => 3,
+        "Pf" => 1,
+        magic => return Err(format!("unsupported PFM type `{}`", magic)),
+    };
+    let size = header_line(bytes, &mut position)?;
+    let size: Vec<usize> = size.split_whitespace()
+        .map(|token| token.parse().map_err(|_| format!("invalid PFM size `{}`", size)))
+        .collect::<Result<_, _>>()?;
+    let [width, height] = size[..] else {
+        return Err("invalid PFM size".to_string())
+    };
+    let scale = header_line(bytes, &mut position)?;
+    let scale: f32 = scale.trim().parse().map_err(|_| format!("invalid PFM scale `{}`", scale))?;
+
+    let data = &bytes[position..];
+    let n_values = width.checked_mul(height)
+        .and_then(|n| n.checked_mul(channels))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("invalid PFM size {}x{}", width, height))?;
+    if data.len() / 4 < n_values {
+        return Err(format!("expected {} values, found {}", n_values, data.len() / 4))
+    }
+    // A negative scale means little endian
+    let values: Vec<f32> = data.chunks_exact(4)
+        .take(n_values)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if scale < 0. { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+        })
+        .collect();
+
+    // Rows are stored from the bottom
+    let pixels = values.chunks_exact(width * channels)
+        .rev()
+        .flat_map(|row| row.chunks_exact(channels))
+        .map(|c| if channels == 1 { Color::new(c[0], c[0], c[0]) } else { Color::new(c[0], c[1], c[2]) })
+        .collect();
+    Ok(RawImage { width, height, pixels, linear: true })
+}
+
+pub fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return BLACK
+    }
+    // Mantissas are taken at the middle of their interval
+    let scale = 2f32.powi(rgbe[3] as i32 - 128 - 8);
+    let mantissa = |m: u8| (m as f32 + 0.5) * scale;
+    Color::new(mantissa(rgbe[0]), mantissa(rgbe[1]), mantissa(rgbe[2]))
+}
+
+/// Decode a Radiance RGBE file, with flat or run length encoded scanlines,
+/// stored from the top.
+pub fn decode_hdr(bytes: &[u8]) -> Result<RawImage, String> {
+    let mut position = 0;
+    let magic = header_line(bytes, &mut position)?;
+    if !magic.starts_with("#?") {
+        return Err("not a Radiance HDR file".to_string())
+    }
+    // Variables until an empty line, then the resolution
+    loop {
+        let line = header_line(bytes, &mut position)?;
+        if line.is_empty() {
+            break
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported HDR format `{}`", format))
+            }
+        }
+    }
+    let resolution = header_line(bytes, &mut position)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse::<usize>(), width.parse::<usize>()),
+        _ => return Err(format!("unsupported HDR orientation `{}`", resolution)),
+    };
+    let (height, width) = match (height, width) {
+        (Ok(height), Ok(width)) => (height, width),
+        _ => return Err(format!("invalid HDR resolution `{}`", resolution)),
+    };
+
+    // Bytes taken at least by a scanline, as runs of 127 values when encoded
+    let min_line = if (8..0x8000).contains(&width) { Some(4 + 8 * width.div_ceil(127)) } else { width.checked_mul(4) };
+    let size = width.checked_mul(height)
+        .filter(|&size| size > 0)
+        .filter(|_| min_line.and_then(|n| n.checked_mul(height)).is_some_and(|n| n <= bytes.len() - position))
+        .ok_or_else(|| format!("invalid HDR size {}x{}", width, height))?;
+
+    let truncated = || "truncated HDR data".to_string();
+    let mut pixels = Vec::with_capacity(size);
+    let mut line = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        let header = bytes.get(position..position + 4).ok_or_else(truncated)?;
+        let encoded = (8..0x8000).contains(&width)
+            && header[0] == 2 && header[1] == 2 && ((header[2] as usize) << 8 | header[3] as usize) == width;
+        if encoded {
+            // Each component on its own, as runs or literal spans
+            position += 4;
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = *bytes.get(position).ok_or_else(truncated)? as usize;
+                    let (run, count) = if count > 128 { (true, count - 128) } else { (false, count) };
+                    if count == 0 || x + count > width {
+                        return Err("invalid HDR run length".to_string())
+                    }
+                    if run {
+                        let value = *bytes.get(position + 1).ok_or_else(truncated)?;
+                        line[x..x + count].iter_mut().for_each(|pixel| pixel[channel] = value);
+                        position += 2;
+                    } else {
+                        let values = bytes.get(position + 1..position + 1 + count).ok_or_else(truncated)?;
+                        line[x..x + count].iter_mut().zip(values).for_each(|(pixel, &value)| pixel[channel] = value);
+                        position += 1 + count;
+                    }
+                    x += count;
+                }
+            }
+        } else {
+            let data = bytes.get(position..position + 4 * width).ok_or_else(truncated)?;
+            for (pixel, rgbe) in line.iter_mut().zip(data.chunks_exact(4)) {
+                *pixel = [rgbe[0], rgbe[1], rgbe[2], rgbe[3]];
+            }
+            position += 4 * width;
+        }
+        pixels.extend(line.iter().map(|&rgbe| from_rgbe(rgbe)));
+    }
+    Ok(RawImage { width, height, pixels, linear: true })
+}
+
+/// Encoder of an image given row by row from the top. High dynamic range
+/// writers take the linear radiance, the others take components already
+/// encoded for display and clamp them to [0, 1].
+pub trait ImageWriter {
+    fn write(&self, output: &mut dyn Write, image: &Image) -> io::Result<()>;
+
+    fn is_hdr(&self) -> bool {
+        false
+    }
+}
+
+/// Component in [0, 1] to an integer in [0, max].
+fn quantize(x: f32, max: u16) -> u16 {
+    (x.clamp(0., 1.) * max as f32).round() as u16
+}
+
+/// ASCII PPM (P3), with 8 bits per component.
+pub struct PpmWriter;
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, output: &mut dyn Write, image: &Image) -> io::Result<()> {
+        let (width, height) = (image.width, image.height);
+        let mut output = io::BufWriter::new(output);
+        write!(output, "P3\n{} {}\n255\n", width, height)?;
+        for color in &image.pixels {
+            writeln!(output, "{} {} {}", quantize(color.r, 255), quantize(color.g, 255), quantize(color.b, 255))?;
+        }
+        output.flush()
+    }
+}
+
+/// RGB PNG, with 8 or 16 bits per component.
+pub struct PngWriter {
+    pub sixteen_bits: bool,
+}
+
+impl ImageWriter for PngWriter {
+    fn write(&self, output: &mut dyn Write, image: &Image) -> io::Result<()> {
+        let (width, height) = (image.width, image.height);
+        let mut encoder = png::Encoder::new(output, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut data = Vec::with_capacity(width * height * if self.sixteen_bits { 6 } else { 3 });
+        if self.sixteen_bits {
+            encoder.set_depth(png::BitDepth::Sixteen);
+            for color in &image.pixels {
+                for c in [color.r, color.g, color.b] {
+                    data.extend_from_slice(&quantize(c, 65535).to_be_bytes());
+                }
+            }
+        } else {
+            encoder.set_depth(png::BitDepth::Eight);
+            for color in &image.pixels {
+                data.extend([color.r, color.g, color.b].map(|c| quantize(c, 255) as u8));
+            }
+        }
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&data).map_err(png_error)?;
+        writer.finish().map_err(png_error)
+    }
+}
+
+fn png_error(error: png::EncodingError) -> io::Error {
+    match error {
+        png::EncodingError::IoError(error) => error,
+        error => io::Error::new(io::ErrorKind::InvalidInput, error),
+    }
+}
+
+/// Portable float map: little endian 32 bit floats, from the bottom row.
+pub struct PfmWriter;
+
+impl ImageWriter for PfmWriter {
+    fn write(&self, output: &mut dyn Write, image: &Image) -> io::Result<()> {
+        let (width, height) = (image.width, image.height);
+        let mut output = io::BufWriter::new(output);
+        // A negative scale means little endian
+        write!(output, "PF\n{} {}\n-1.0\n", width, height)?;
+        for row in image.rows().rev() {
+            for color in row {
+                for c in [color.r, color.g, color.b] {
+                    output.write_all(&c.to_le_bytes())?;
+                }
+            }
+        }
+        output.flush()
+    }
+
+    fn is_hdr(&self) -> bool {
+        true
+    }
+}
+
+/// Radiance HDR, with uncompressed RGBE pixels: a shared exponent and
+/// three 8 bit mantissas.
+pub struct HdrWriter;
+
+pub fn to_rgbe(color: &Color) -> [u8; 4] {
+    let max = color.r.max(color.g).max(color.b);
+    if max.is_nan() || max < 1e-32 {
+        return [0; 4]
+    }
+    // Infinite radiance saturates to the largest value of the format
+    let max = max.min(255. / 256. * 2f32.powi(127));
+    // max = m * 2^exponent with m in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / 2f32.powi(exponent) >= 1. {
+        exponent += 1;
+    }
+    let scale = 256. / 2f32.powi(exponent);
+    let mantissa = |c: f32| (c.max(0.) * scale).min(255.) as u8;
+    [mantissa(color.r), mantissa(color.g), mantissa(color.b), (exponent + 128).clamp(0, 255) as u8]
+}
+
+impl ImageWriter for HdrWriter {
+    fn write(&self, output: &mut dyn Write, image: &Image) -> io::Result<()> {
+        let (width, height) = (image.width, image.height);
+        let mut output = io::BufWriter::new(output);
+        write!(output, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+        for color in &image.pixels {
+            output.write_all(&to_rgbe(color))?;
+        }
+        output.flush()
+    }
+
+    fn is_hdr(&self) -> bool {
+        true
+    }
+}
+
+/// Output formats, with their writers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Png16,
+    Pfm,
+    Hdr,
+    Exr(ExrPixelType, ExrCompression),
+}
+
+impl ImageFormat {
+    /// Format matching the extension of `path`. PNG files have 8 bits per
+    /// component, and EXR files ZIP compressed halfs.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "pfm" => Some(ImageFormat::Pfm),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr(ExrPixelType::Half, ExrCompression::Zip)),
+            _ => None,
+        }
+    }
+
+    pub fn writer(&self) -> Box<dyn ImageWriter> {
+        match self {
+            ImageFormat::Ppm => Box::new(PpmWriter),
+            ImageFormat::Png => Box::new(PngWriter { sixteen_bits: false }),
+            ImageFormat::Png16 => Box::new(PngWriter { sixteen_bits: true }),
+            ImageFormat::Pfm => Box::new(PfmWriter),
+            ImageFormat::Hdr => Box::new(HdrWriter),
+            ImageFormat::Exr(pixel_type, compression) => Box::new(ExrWriter { pixel_type: *pixel_type, compression: *compression }),
+        }
+    }
+}
+
+/// Write an image to a file with `writer`.
+pub fn write_image<P: AsRef<Path>>(path: P, writer: &dyn ImageWriter, image: &Image) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    writer.write(&mut file, image)
+}
+
+#[test]
+fn test_decode_ppm() {
+    let image = decode_ppm(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 51\n").unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(image.pixels[0].r, 1.);
+    assert_eq!(image.pixels[1].b, 0.2);
+
+    let image = decode_ppm(b"P6 1 1 255\n\xff\x00\x80").unwrap();
+    assert_eq!(image.pixels[0].r, 1.);
+    assert_eq!(image.pixels[0].g, 0.);
+
+    assert!(decode_ppm(b"P3\n2 2\n255\n1 2 3\n").is_err());
+    // Sizes that overflow, or that the data cannot hold
+    assert!(decode_ppm(b"P6 18446744073709551615 2 255\n\x00").is_err());
+    assert!(decode_ppm(b"P6 100000 100000 255\n\x00").is_err());
+}
+
+#[test]
+fn test_write_png() {
+    let pixels = Image::from_pixels(2, 1, vec![Color::new(1., 0.5, 0.), Color::new(0.2, 2., -1.)]);
+    for (writer, tolerance) in [(PngWriter { sixteen_bits: false }, 1. / 255.), (PngWriter { sixteen_bits: true }, 1. / 65535.)] {
+        let mut bytes = Vec::new();
+        writer.write(&mut bytes, &pixels).unwrap();
+        let image = decode_png(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert!((image.pixels[0].g - 0.5).abs() <= tolerance);
+        assert!((image.pixels[1].r - 0.2).abs() <= tolerance);
+        // Out of range values are clamped
+        assert_eq!((image.pixels[1].g, image.pixels[1].b), (1., 0.));
+    }
+
+    let mut bytes = Vec::new();
+    PpmWriter.write(&mut bytes, &pixels).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), "P3\n2 1\n255\n255 128 0\n51 255 0\n");
+}
+
+#[test]
+fn test_write_hdr() {
+    assert_eq!(to_rgbe(&Color::new(1., 0.5, 0.)), [128, 64, 0, 129]);
+    assert_eq!(to_rgbe(&Color::new(0., 0., 0.)), [0; 4]);
+    assert_eq!(to_rgbe(&Color::new(15., 15., 15.))[3], 132);
+    assert_eq!(to_rgbe(&Color::new(f32::INFINITY, 0., 0.)), [255, 0, 0, 255]);
+
+    let mut bytes = Vec::new();
+    PfmWriter.write(&mut bytes, &Image::from_pixels(1, 2, vec![Color::new(1., 2., 3.), Color::new(4., 5., 6.)])).unwrap();
+    assert!(bytes.starts_with(b"PF\n1 2\n-1.0\n"));
+    // Bottom row first
+    assert_eq!(&bytes[bytes.len() - 24..bytes.len() - 20], &4f32.to_le_bytes());
+}
+
+#[test]
+fn test_decode_hdr_pfm() {
+    let pixels = vec![Color::new(1., 2., 3.), Color::new(0.25, 0., 100.), Color::new(4., 5., 6.), BLACK];
+    let image = Image::from_pixels(2, 2, pixels.clone());
+    let mut bytes = Vec::new();
+    PfmWriter.write(&mut bytes, &image).unwrap();
+    let decoded = decode_pfm(&bytes).unwrap();
+    assert!(decoded.linear && (decoded.width, decoded.height) == (2, 2));
+    assert_eq!(decoded.pixels, pixels);
+
+    let mut bytes = Vec::new();
+    HdrWriter.write(&mut bytes, &image).unwrap();
+    let decoded = decode_hdr(&bytes).unwrap();
+    for (a, b) in decoded.pixels.iter().zip(&pixels) {
+        assert!((a.r - b.r).abs() <= b.r / 128. && (a.b - b.b).abs() <= b.b / 128.);
+    }
+
+    // Run length encoded scanline of 8 pixels: a run of 8 for R and G, and
+    // literal values for B and E
+    let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n\x02\x02\x00\x08".to_vec();
+    bytes.extend_from_slice(&[136, 128, 136, 64, 8, 0, 1, 2, 3, 4, 5, 6, 7, 136, 129]);
+    let decoded = decode_hdr(&bytes).unwrap();
+    assert_eq!(decoded.pixels.len(), 8);
+    assert_eq!(decoded.pixels[0], from_rgbe([128, 64, 0, 129]));
+    assert_eq!(decoded.pixels[7], from_rgbe([128, 64, 7, 129]));
+
+    // Sizes that overflow, or that the data cannot hold
+    assert!(decode_pfm(b"PF\n4294967296 4294967296\n-1\n\0\0\0\0").is_err());
+    assert!(decode_pfm(b"PF\n0 1\n-1\n").is_err());
+    assert!(decode_hdr(b"#?RADIANCE\n\n-Y 4294967296 +X 4294967296\n\0\0\0\0").is_err());
+    assert!(decode_hdr(b"#?RADIANCE\n\n-Y 100000 +X 100000\n\0\0\0\0").is_err());
+    assert!(decode_hdr(b"#?RADIANCE\n\n-Y 1000000000000 +X 0\n\0\0\0\0").is_err());
+}
@@ -1,10 +1,26 @@
 pub mod parameters;
+pub mod random;
 pub mod render;
 pub mod color;
 pub mod ray;
 pub mod camera;
 pub mod primitives;
+pub mod aabb;
+pub mod bvh;
+pub mod lights;
+pub mod background;
+pub mod mesh;
+pub mod obj;
 pub mod vector3;
 pub mod material;
+pub mod microfacet;
+pub mod principled;
+pub mod texture;
+pub mod noise;
+pub mod image;
+pub mod image_io;
+pub mod tonemap;
+pub mod exr;
 pub mod config;
+pub mod scene_file;
 pub mod scenes;
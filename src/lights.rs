@@ -0,0 +1,70 @@
+use nalgebra::Vector3;
+use rand::prelude::*;
+
+use crate::{primitives::Primitive, random};
+
+#[cfg(test)]
+use crate::{primitives::*, material::*, color::Color, parameters::ORIGIN};
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+/// Emissive primitives of a scene, sampled explicitly at each diffuse hit.
+/// The light to aim at is picked uniformly, so the density of a direction is
+/// the average of the densities of all the lights.
+#[derive(Default)]
+pub struct LightList<'a> {
+    lights: Vec<&'a dyn Primitive>,
+}
+
+impl<'a> LightList<'a> {
+    pub fn new(lights: Vec<&'a dyn Primitive>) -> LightList<'a> {
+        LightList { lights }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Direction from `origin` towards a random point of a random light.
+    pub fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        let index = random::rng().gen_range(0..self.lights.len());
+        self.lights[index].sample(origin)
+    }
+
+    pub fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        if self.lights.is_empty() {
+            return 0.
+        }
+        let sum: f32 = self.lights.iter()
+            .map(|light| light.pdf(origin, direction))
+            .sum();
+        sum / self.lights.len() as f32
+    }
+}
+
+#[test]
+fn test_light_pdf() {
+    let light = Material::Light(Light::new(Color::new(1., 1., 1.)));
+    let rectangle = RectangleXZ::new(-1., 1., -1., 1., 2., light.clone());
+    let sphere = Sphere::new(Vector3::new(0., -3., 0.), 1., light);
+    let lights = LightList::new(vec![&rectangle, &sphere]);
+
+    // 2x2 rectangle seen from 2 units straight below: d^2 / (cos * area)
+    let up = Vector3::new(0., 1., 0.);
+    assert_approx_eq!(lights.pdf(&ORIGIN, &up), 0.5 * (4. / 4.));
+
+    // Sphere of radius 1 at distance 3: uniform over the cone
+    let down = Vector3::new(0., -1., 0.);
+    let cos_theta_max = (1. - 1. / 9f32).sqrt();
+    let expected = 1. / (2. * std::f32::consts::PI * (1. - cos_theta_max));
+    assert_approx_eq!(lights.pdf(&ORIGIN, &down), 0.5 * expected, 1e-3);
+
+    for _ in 0..100 {
+        let direction = lights.sample(&ORIGIN);
+        assert!(lights.pdf(&ORIGIN, &direction) > 0.);
+    }
+}
@@ -1,7 +1,223 @@
+use std::{path::PathBuf, process};
+use clap::{Parser, ValueEnum};
+
 use rtiow::scenes::*;
-use rtiow::render::render;
+use rtiow::scene_file::load_scene;
+use rtiow::config::Config;
+use rtiow::render::render_with;
+use rtiow::image_io::ImageFormat;
+use rtiow::exr::{ExrPixelType, ExrCompression};
+use rtiow::tonemap::ToneMapper;
+use rtiow::random;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Ppm,
+    /// PNG with 8 bits per component
+    Png,
+    /// PNG with 16 bits per component
+    Png16,
+    /// Portable float map
+    Pfm,
+    /// Radiance RGBE
+    Hdr,
+    /// OpenEXR, see --exr-pixel-type and --exr-compression
+    Exr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum ToneMap {
+    Clamp,
+    Reinhard,
+    Hable,
+    Aces,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum PixelType {
+    Half,
+    Float,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Compression {
+    None,
+    Zip,
+}
+
+
+/// Render a built-in scene or a scene file.
+#[derive(Parser, Debug)]
+#[command(name = "rtiow")]
+struct Args {
+    /// Name of a built-in scene, or path to a TOML scene file
+    #[arg(default_value = "cornell_box")]
+    scene: String,
+
+    /// Image width. The height follows the aspect ratio of the scene, which is fixed by its camera
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Image height. The width follows the aspect ratio of the scene, which is fixed by its camera
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// Samples per pixel
+    #[arg(long)]
+    spp: Option<usize>,
+
+    /// Maximum number of bounces
+    #[arg(long)]
+    depth: Option<usize>,
+
+    /// Number of worker threads, all the cores by default
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Output file
+    #[arg(short, long, default_value = "zebi.ppm")]
+    output: PathBuf,
+
+    /// Output format, guessed from the extension of the output by default
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Exposure compensation in stops, for low dynamic range formats
+    #[arg(long)]
+    exposure: Option<f32>,
+
+    /// Tone mapping operator, for low dynamic range formats
+    #[arg(long, value_enum)]
+    tone_map: Option<ToneMap>,
+
+    /// Luminance mapped to white by the Reinhard operator
+    #[arg(long)]
+    white_point: Option<f32>,
+
+    /// Precision of the EXR channels
+    #[arg(long, value_enum, default_value = "half")]
+    exr_pixel_type: PixelType,
+
+    /// Compression of the EXR scanlines
+    #[arg(long, value_enum, default_value = "zip")]
+    exr_compression: Compression,
+
+    /// Seed of the random generators, for reproducible renders
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Print the names of the built-in scenes and exit
+    #[arg(long)]
+    list_scenes: bool,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1)
+}
+
+fn load(name: &str) -> Config {
+    if let Some(scene) = scene_by_name(name) {
+        return scene
+    }
+    if name.ends_with(".toml") || PathBuf::from(name).is_file() {
+        return load_scene(name).unwrap_or_else(|e| fail(e.to_string()))
+    }
+    fail(format!("unknown scene `{}`, see --list-scenes", name))
+}
 
 fn main() {
-    let scene = cornell_box();
-    render(scene, "zebi.ppm");
+    let args = Args::parse();
+
+    if args.list_scenes {
+        for (name, _) in SCENES {
+            println!("{}", name);
+        }
+        return
+    }
+
+    let pixel_type = match args.exr_pixel_type {
+        PixelType::Half => ExrPixelType::Half,
+        PixelType::Float => ExrPixelType::Float,
+    };
+    let compression = match args.exr_compression {
+        Compression::None => ExrCompression::None,
+        Compression::Zip => ExrCompression::Zip,
+    };
+    let format = args.format
+        .map(|format| match format {
+            Format::Ppm => ImageFormat::Ppm,
+            Format::Png => ImageFormat::Png,
+            Format::Png16 => ImageFormat::Png16,
+            Format::Pfm => ImageFormat::Pfm,
+            Format::Hdr => ImageFormat::Hdr,
+            Format::Exr => ImageFormat::Exr(pixel_type, compression),
+        })
+        .or_else(|| ImageFormat::from_path(&args.output))
+        .map(|format| match format {
+            ImageFormat::Exr(..) => ImageFormat::Exr(pixel_type, compression),
+            format => format,
+        })
+        .unwrap_or_else(|| fail(format!("cannot guess the format of `{}`, use --format", args.output.display())));
+
+    // Scenes may be generated randomly, seed them too
+    if let Some(seed) = args.seed {
+        random::seed(seed);
+    }
+    let mut scene = load(&args.scene);
+
+    let aspect_ratio = scene.width as f32 / scene.height as f32;
+    match (args.width, args.height) {
+        // The camera was built for the aspect ratio of the scene
+        (Some(width), Some(height)) if (height as f32 - width as f32 / aspect_ratio).abs() > 1. => {
+            fail(format!("{}x{} does not match the aspect ratio of the scene, {}x{}", width, height, scene.width, scene.height))
+        }
+        (Some(width), Some(height)) => (scene.width, scene.height) = (width, height),
+        (Some(width), None) => (scene.width, scene.height) = (width, ((width as f32 / aspect_ratio).round() as usize).max(1)),
+        (None, Some(height)) => (scene.width, scene.height) = (((height as f32 * aspect_ratio).round() as usize).max(1), height),
+        (None, None) => {}
+    }
+    if scene.width == 0 || scene.height == 0 {
+        fail("the image must not be empty".to_string())
+    }
+    if args.spp == Some(0) {
+        fail("at least one sample per pixel is needed".to_string())
+    }
+    if let Some(spp) = args.spp {
+        scene.samples_per_pixel = spp;
+    }
+    if args.depth == Some(0) {
+        fail("the ray depth must be at least 1".to_string())
+    }
+    if let Some(depth) = args.depth {
+        scene.depth = depth;
+    }
+    if args.threads == Some(0) {
+        fail("at least one thread is needed".to_string())
+    }
+    if let Some(exposure) = args.exposure {
+        scene.display.exposure = exposure;
+    }
+    if args.white_point.is_some_and(|white| white <= 0.) {
+        fail("the white point must be positive".to_string())
+    }
+    let white = args.white_point.unwrap_or(f32::INFINITY);
+    match args.tone_map {
+        Some(ToneMap::Clamp) => scene.display.tone_mapper = ToneMapper::Clamp,
+        Some(ToneMap::Reinhard) => scene.display.tone_mapper = ToneMapper::Reinhard { white },
+        Some(ToneMap::Hable) => scene.display.tone_mapper = ToneMapper::Hable,
+        Some(ToneMap::Aces) => scene.display.tone_mapper = ToneMapper::Aces,
+        None => {
+            if let (ToneMapper::Reinhard { .. }, Some(white)) = (scene.display.tone_mapper, args.white_point) {
+                scene.display.tone_mapper = ToneMapper::Reinhard { white };
+            }
+        }
+    }
+    scene.threads = args.threads;
+    scene.seed = args.seed;
+
+    let output = args.output.display().to_string();
+    if let Err(error) = render_with(&scene, &output, format.writer().as_ref()) {
+        fail(format!("{}: {}", output, error))
+    }
 }
@@ -1,30 +1,87 @@
+use std::sync::Arc;
 use rand::Rng;
 use nalgebra::Vector3;
 
-use crate::{ray::*, ray::HitRecord, color::*, vector3::*};
+use crate::{ray::*, ray::HitRecord, color::*, vector3::*, texture::*, microfacet::*, principled::Principled, random};
+
+/// Direction sampled by a material at a hit, with what is needed to weight it.
+#[derive(Debug, Clone, Copy)]
+pub struct ScatterRecord {
+    pub ray: Ray,
+    /// BSDF times the cosine with the normal, for the sampled direction. For
+    /// specular materials it is directly the attenuation of the ray.
+    pub bsdf: Color,
+    /// Density of the direction with respect to solid angle, meaningless
+    /// for specular materials.
+    pub pdf: f32,
+    /// Specular materials scatter in a few precise directions, that cannot
+    /// be evaluated nor reached by light sampling.
+    pub is_specular: bool,
+}
+
+impl ScatterRecord {
+    pub fn new(ray: Ray, bsdf: Color, pdf: f32) -> ScatterRecord {
+        ScatterRecord { ray, bsdf, pdf, is_specular: false }
+    }
+
+    pub fn specular(ray: Ray, attenuation: Color) -> ScatterRecord {
+        ScatterRecord { ray, bsdf: attenuation, pdf: 0., is_specular: true }
+    }
+
+    /// Factor applied to the light coming back along the ray: the BSDF
+    /// divided by the density.
+    pub fn attenuation(&self) -> Color {
+        if self.is_specular {
+            self.bsdf
+        } else if self.pdf > 0. {
+            self.bsdf.scale(1. / self.pdf)
+        } else {
+            BLACK
+        }
+    }
+}
 
 pub trait Scatterable {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Ray, Color)>;
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord>;
 
     fn emitted(&self) -> Color {
         BLACK
     }
+
+    /// BSDF times the cosine with the normal, for light arriving from
+    /// `direction`. Only used when the material is not specular.
+    fn eval(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Vector3<f32>) -> Color {
+        BLACK
+    }
+
+    /// Density with respect to solid angle of `scatter` picking `direction`.
+    fn pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Vector3<f32>) -> f32 {
+        0.
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
+    Conductor(Conductor),
     Dielectric(Dielectric),
+    RoughDielectric(RoughDielectric),
+    Principled(Principled),
+    Plastic(Plastic),
     Light(Light),
 } 
 
 impl Scatterable for Material {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
         match self {
             Material::Lambertian(l) => l.scatter(ray, hit_record),
             Material::Metal(m)=> m.scatter(ray, hit_record),
+            Material::Conductor(c) => c.scatter(ray, hit_record),
             Material::Dielectric(d) => d.scatter(ray, hit_record),
+            Material::RoughDielectric(d) => d.scatter(ray, hit_record),
+            Material::Principled(p) => p.scatter(ray, hit_record),
+            Material::Plastic(p) => p.scatter(ray, hit_record),
             Material::Light(l) => l.scatter(ray, hit_record),
         }
     }
@@ -33,10 +90,46 @@ impl Scatterable for Material {
         match self {
             Material::Lambertian(l) => l.emitted(),
             Material::Metal(m) => m.emitted(),
+            Material::Conductor(c) => c.emitted(),
             Material::Dielectric(d) => d.emitted(),
+            Material::RoughDielectric(d) => d.emitted(),
+            Material::Principled(p) => p.emitted(),
+            Material::Plastic(p) => p.emitted(),
             Material::Light(l) => l.emitted(),
         }
     }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> Color {
+        match self {
+            Material::Lambertian(l) => l.eval(ray, hit_record, direction),
+            Material::Metal(m) => m.eval(ray, hit_record, direction),
+            Material::Conductor(c) => c.eval(ray, hit_record, direction),
+            Material::Dielectric(d) => d.eval(ray, hit_record, direction),
+            Material::RoughDielectric(d) => d.eval(ray, hit_record, direction),
+            Material::Principled(p) => p.eval(ray, hit_record, direction),
+            Material::Plastic(p) => p.eval(ray, hit_record, direction),
+            Material::Light(l) => l.eval(ray, hit_record, direction),
+        }
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> f32 {
+        match self {
+            Material::Lambertian(l) => l.pdf(ray, hit_record, direction),
+            Material::Metal(m) => m.pdf(ray, hit_record, direction),
+            Material::Conductor(c) => c.pdf(ray, hit_record, direction),
+            Material::Dielectric(d) => d.pdf(ray, hit_record, direction),
+            Material::RoughDielectric(d) => d.pdf(ray, hit_record, direction),
+            Material::Principled(p) => p.pdf(ray, hit_record, direction),
+            Material::Plastic(p) => p.pdf(ray, hit_record, direction),
+            Material::Light(l) => l.pdf(ray, hit_record, direction),
+        }
+    }
+}
+
+impl Material {
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, Material::Light(_))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -46,12 +139,12 @@ pub struct Light {
 
 impl Light {
     pub fn new(color: Color) -> Light {
-        return Light { color }
+        Light { color }
     }
 }
 
 impl Scatterable for Light {
-    fn scatter(&self, _ray: &Ray, _hit_record: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, _ray: &Ray, _hit_record: &HitRecord) -> Option<ScatterRecord> {
         None
     }
 
@@ -62,71 +155,253 @@ impl Scatterable for Light {
 
 #[derive(Debug, Clone)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
+        Lambertian { albedo: Arc::new(SolidColor::new(albedo)) }
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Lambertian {
         Lambertian { albedo }
     }
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord) -> Option<(Ray, Color)> {
-        let mut scatter_direction = hit_record.normal + Vector3::random_unit_vector();
-        if Vector3::near_zero(&scatter_direction) {
-            scatter_direction = hit_record.normal;
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        let direction = Onb::from_w(&hit_record.normal).local(&Vector3::random_cosine_direction());
+        let scattered = Ray::with_time(hit_record.position, direction, ray.time);
+        let bsdf = self.eval(ray, hit_record, &direction);
+        let pdf = self.pdf(ray, hit_record, &direction);
+        Some(ScatterRecord::new(scattered, bsdf, pdf))
+    }
 
+    fn eval(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> Color {
+        let cosine = hit_record.normal.dot(direction) / direction.norm();
+        if cosine <= 0. {
+            return BLACK
         }
-        let scattered = Ray::new(hit_record.position, scatter_direction);
-        let attenuation = self.albedo;
-        // println!("Scattered ray:");
-        // println!("-- Normal: {:?}", &hit_record.normal);
-        // println!("-- Incoming: {:?}", &hit_record.incoming);
-        // println!("-- Origin: {:?}", &hit_record.position);
-        // println!("-- Direction: {:?}\n", &scatter_direction);
-        Some((scattered, attenuation))
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.position);
+        albedo.scale(cosine / std::f32::consts::PI)
+    }
+
+    fn pdf(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> f32 {
+        let cosine = hit_record.normal.dot(direction) / direction.norm();
+        cosine.max(0.) / std::f32::consts::PI
     }
 }
 
+/// The fuzz is read from the luminance of a texture, so that a noise can
+/// make parts of the surface rougher than others.
 #[derive(Debug, Clone)]
 pub struct Metal {
-    pub albedo: Color,
-    pub fuzz: f32,
+    pub albedo: Arc<dyn Texture>,
+    pub fuzz: Arc<dyn Texture>,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f32) -> Metal {
+        Metal {
+            albedo: Arc::new(SolidColor::new(albedo)),
+            fuzz: Arc::new(SolidColor::new(Color::new(fuzz, fuzz, fuzz))),
+        }
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: Arc<dyn Texture>) -> Metal {
         Metal { albedo, fuzz }
     }
 }
 
+impl Metal {
+    fn fuzz_at(&self, hit_record: &HitRecord) -> f32 {
+        self.fuzz.value(hit_record.u, hit_record.v, &hit_record.position).luminance()
+    }
+}
+
 impl Scatterable for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Ray, Color)> {
-        let reflected = Vector3::reflect(&ray.direction, &hit_record.normal);
-        let scattered = Ray::new(
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        let reflected = Vector3::reflect(&ray.direction.normalize(), &hit_record.normal);
+        let fuzz = self.fuzz_at(hit_record);
+        let scattered = Ray::with_time(
             hit_record.position, 
-            reflected + Vector3::random_in_unit_sphere() * self.fuzz);
-        let attenuation = self.albedo;
-        if scattered.direction.dot(&hit_record.normal) > 0. {
-            Some((scattered, attenuation))
-        } else {
-            None
+            reflected + Vector3::random_in_unit_sphere() * fuzz,
+            ray.time);
+        if scattered.direction.dot(&hit_record.normal) <= 0. {
+            return None
+        }
+        if fuzz <= 0. {
+            let attenuation = self.albedo.value(hit_record.u, hit_record.v, &hit_record.position);
+            return Some(ScatterRecord::specular(scattered, attenuation))
+        }
+        let bsdf = self.eval(ray, hit_record, &scattered.direction);
+        let pdf = self.pdf(ray, hit_record, &scattered.direction);
+        Some(ScatterRecord::new(scattered, bsdf, pdf))
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> Color {
+        // The BSDF is defined by the sampling: the attenuation of a sampled
+        // ray is always the albedo.
+        if direction.dot(&hit_record.normal) <= 0. {
+            return BLACK
+        }
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.position);
+        albedo.scale(self.pdf(ray, hit_record, direction))
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> f32 {
+        // The scattered direction points to a uniform point of the ball of
+        // radius `fuzz` around the mirror direction. The density of a
+        // direction is the volume of the ball along it, in spherical
+        // coordinates: the integral of t^2 dt over the chord, over the volume.
+        let fuzz = self.fuzz_at(hit_record);
+        if fuzz <= 0. {
+            return 0.
+        }
+        let reflected = Vector3::reflect(&ray.direction.normalize(), &hit_record.normal);
+        let b = direction.normalize().dot(&reflected);
+        let discriminant = b * b - 1. + fuzz * fuzz;
+        if discriminant <= 0. {
+            return 0.
         }
+        let t1 = b + discriminant.sqrt();
+        if t1 <= 0. {
+            return 0.
+        }
+        let t0 = (b - discriminant.sqrt()).max(0.);
+        let volume = 4. / 3. * std::f32::consts::PI * fuzz.powi(3);
+        (t1.powi(3) - t0.powi(3)) / (3. * volume)
+    }
+}
+
+/// Rough metal, made of GGX microfacets reflecting light as given by the
+/// Fresnel equations for the complex index of refraction `eta + i k` of the
+/// metal, one per RGB channel.
+#[derive(Debug, Clone)]
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: f32,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f32) -> Conductor {
+        Conductor { eta, k, roughness }
+    }
+
+    pub fn gold(roughness: f32) -> Conductor {
+        Conductor::new(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603), roughness)
+    }
+
+    pub fn copper(roughness: f32) -> Conductor {
+        Conductor::new(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142), roughness)
+    }
+
+    pub fn aluminum(roughness: f32) -> Conductor {
+        Conductor::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), roughness)
+    }
+
+    pub fn silver(roughness: f32) -> Conductor {
+        Conductor::new(Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147), roughness)
+    }
+
+    // Directions towards the viewer and the light in the frame of the
+    // normal, with the microfacet distribution
+    fn local(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>, Ggx) {
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        let wi = onb.to_local(&direction.normalize());
+        (wo, wi, Ggx::from_roughness(self.roughness))
+    }
+}
+
+impl Scatterable for Conductor {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0. {
+            return None
+        }
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() {
+            let reflected = Vector3::reflect(&ray.direction.normalize(), &hit_record.normal);
+            let scattered = Ray::with_time(hit_record.position, reflected, ray.time);
+            return Some(ScatterRecord::specular(scattered, fresnel_conductor(wo.z, &self.eta, &self.k)))
+        }
+
+        let h = ggx.sample_visible_normal(&wo);
+        let wi = Vector3::reflect(&-wo, &h);
+        if wi.z <= 0. {
+            return None
+        }
+        let direction = onb.local(&wi);
+        let scattered = Ray::with_time(hit_record.position, direction, ray.time);
+        let bsdf = self.eval(ray, hit_record, &direction);
+        let pdf = self.pdf(ray, hit_record, &direction);
+        Some(ScatterRecord::new(scattered, bsdf, pdf))
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> Color {
+        let (wo, wi, ggx) = self.local(ray, hit_record, direction);
+        if wo.z <= 0. || wi.z <= 0. || ggx.is_smooth() {
+            return BLACK
+        }
+        let h = (wo + wi).normalize();
+        // F D G / (4 cos_o cos_i), times cos_i
+        let fresnel = fresnel_conductor(wo.dot(&h), &self.eta, &self.k);
+        fresnel.scale(ggx.d(&h) * ggx.g(&wo, &wi) / (4. * wo.z))
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> f32 {
+        let (wo, wi, ggx) = self.local(ray, hit_record, direction);
+        if wo.z <= 0. || wi.z <= 0. || ggx.is_smooth() {
+            return 0.
+        }
+        // Jacobian of the reflection about the microfacet normal
+        let h = (wo + wi).normalize();
+        ggx.visible_normal_pdf(&wo, &h) / (4. * wo.dot(&h))
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Dielectric {
-    pub index_of_refraction: f32
+    pub index_of_refraction: f32,
+    /// Absorption coefficient inside the material, per unit of length.
+    pub absorption: Color,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f32) -> Dielectric {
-        Dielectric { index_of_refraction }
+        Dielectric { index_of_refraction, absorption: BLACK }
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> Dielectric {
+        self.absorption = absorption;
+        self
+    }
+
+    /// Absorb light so that `color` is left after traveling `distance`.
+    pub fn with_transmittance(self, color: Color, distance: f32) -> Dielectric {
+        self.with_absorption(absorption_for_transmittance(color, distance))
     }
 }
 
+/// Absorption coefficient leaving `transmittance` after traveling `distance`.
+pub fn absorption_for_transmittance(transmittance: Color, distance: f32) -> Color {
+    transmittance.map(|c| -c.max(1e-6).ln() / distance)
+}
+
+/// Beer-Lambert attenuation of the light along the ray, if it traveled
+/// inside the material to reach the hit. Objects nested inside are not
+/// accounted for: the ray is assumed to come from the same surface.
+fn transmittance(absorption: &Color, ray: &Ray, hit_record: &HitRecord) -> Color {
+    if hit_record.front_face || absorption.is_black() {
+        return WHITE
+    }
+    let distance = hit_record.t * ray.direction.norm();
+    absorption.map(|a| (-a * distance).exp())
+}
+
 fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
     // Use Schlick's approximation for reflectance
     let mut r0 = (1. - ref_idx) / (1. + ref_idx);
@@ -135,9 +410,9 @@ fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
 }
 
 impl Scatterable for Dielectric {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Ray, Color)> {
-        let mut rng = rand::thread_rng();
-        let attenuation = Color::new(1., 1., 1.);
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        let mut rng = random::rng();
+        let attenuation = transmittance(&self.absorption, ray, hit_record);
         let etai_over_etat = if hit_record.front_face { 
             1./self.index_of_refraction 
         } else { 
@@ -150,12 +425,373 @@ impl Scatterable for Dielectric {
         let cannot_refract = etai_over_etat * sin_theta > 1.;
         if cannot_refract || reflectance(cos_theta, etai_over_etat) > rng.gen() {
             let reflected = Vector3::reflect(&unit_direction, &hit_record.normal);
-            let scattered = Ray::new(hit_record.position, reflected);
-            Some((scattered, attenuation))
+            let scattered = Ray::with_time(hit_record.position, reflected, ray.time);
+            Some(ScatterRecord::specular(scattered, attenuation))
         } else {
             let refracted = Vector3::refract(&unit_direction, &hit_record.normal, etai_over_etat);
-            let scattered = Ray::new(hit_record.position, refracted);
-            Some((scattered, attenuation))
+            let scattered = Ray::with_time(hit_record.position, refracted, ray.time);
+            Some(ScatterRecord::specular(scattered, attenuation))
+        }
+    }
+}
+
+/// Frosted glass: GGX microfacets reflecting and refracting light, in the
+/// proportions given by the Fresnel equations (Walter et al. 2007).
+///
+/// As for `Dielectric`, the radiance is not scaled by the squared ratio of
+/// the indices of refraction when crossing the surface, which cancels out
+/// for closed objects.
+#[derive(Debug, Clone)]
+pub struct RoughDielectric {
+    pub index_of_refraction: f32,
+    pub roughness: f32,
+    /// Absorption coefficient inside the material, per unit of length.
+    pub absorption: Color,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f32, roughness: f32) -> RoughDielectric {
+        RoughDielectric { index_of_refraction, roughness, absorption: BLACK }
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> RoughDielectric {
+        self.absorption = absorption;
+        self
+    }
+
+    /// Absorb light so that `color` is left after traveling `distance`.
+    pub fn with_transmittance(self, color: Color, distance: f32) -> RoughDielectric {
+        self.with_absorption(absorption_for_transmittance(color, distance))
+    }
+
+    // Ratio of the index of refraction across the surface over the one on
+    // the side of the ray, whose normal always faces the ray
+    fn eta(&self, hit_record: &HitRecord) -> f32 {
+        if hit_record.front_face {
+            self.index_of_refraction
+        } else {
+            1. / self.index_of_refraction
+        }
+    }
+
+    // Microfacet normal turning `wo` into `wi`, on the side of the surface
+    // normal, or `None` if the microfacet would face away from either of them
+    fn half_vector(wo: &Vector3<f32>, wi: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+        let h = if wi.z > 0. { wo + wi } else { -(wo + eta * wi) };
+        if h.norm_squared() == 0. {
+            return None
+        }
+        let h = if h.z < 0. { -h.normalize() } else { h.normalize() };
+        let reflected = wi.z > 0.;
+        if wo.dot(&h) <= 0. || (wi.dot(&h) > 0.) != reflected {
+            return None
+        }
+        Some(h)
+    }
+}
+
+impl Scatterable for RoughDielectric {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        let mut rng = random::rng();
+        let eta = self.eta(hit_record);
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0. {
+            return None
+        }
+
+        let ggx = Ggx::from_roughness(self.roughness);
+        let h = if ggx.is_smooth() { Vector3::new(0., 0., 1.) } else { ggx.sample_visible_normal(&wo) };
+        let fresnel = fresnel_dielectric(wo.dot(&h), eta);
+        let wi = if fresnel >= 1. || rng.gen::<f32>() < fresnel {
+            Vector3::reflect(&-wo, &h)
+        } else {
+            Vector3::refract(&-wo, &h, 1. / eta)
+        };
+        let direction = onb.local(&wi);
+        let scattered = Ray::with_time(hit_record.position, direction, ray.time);
+
+        if ggx.is_smooth() {
+            return Some(ScatterRecord::specular(scattered, transmittance(&self.absorption, ray, hit_record)))
+        }
+        // Reflections below the surface, and refractions above it, are
+        // masked by the other microfacets
+        if (wi.z > 0.) != (wi.dot(&h) > 0.) || wi.z == 0. {
+            return None
+        }
+        let bsdf = self.eval(ray, hit_record, &direction);
+        let pdf = self.pdf(ray, hit_record, &direction);
+        Some(ScatterRecord::new(scattered, bsdf, pdf))
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> Color {
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() {
+            return BLACK
+        }
+        let eta = self.eta(hit_record);
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        let wi = onb.to_local(&direction.normalize());
+        let h = match RoughDielectric::half_vector(&wo, &wi, eta) {
+            Some(h) => h,
+            None => return BLACK,
+        };
+
+        let fresnel = fresnel_dielectric(wo.dot(&h), eta);
+        let dg = ggx.d(&h) * ggx.g(&wo, &wi);
+        // BSDF times the cosine of `wi`, which cancels out
+        let value = if wi.z > 0. {
+            fresnel * dg / (4. * wo.z)
+        } else {
+            let denominator = wo.dot(&h) + eta * wi.dot(&h);
+            (1. - fresnel) * dg * eta * eta * (wi.dot(&h) * wo.dot(&h)).abs() / (wo.z * denominator * denominator)
+        };
+        transmittance(&self.absorption, ray, hit_record).scale(value)
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> f32 {
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() {
+            return 0.
+        }
+        let eta = self.eta(hit_record);
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        let wi = onb.to_local(&direction.normalize());
+        let h = match RoughDielectric::half_vector(&wo, &wi, eta) {
+            Some(h) => h,
+            None => return 0.,
+        };
+
+        // Density of the microfacet normal, times the chances of reflecting
+        // or refracting, times the Jacobian of the mapping from `h` to `wi`
+        let fresnel = fresnel_dielectric(wo.dot(&h), eta);
+        let pdf_h = ggx.visible_normal_pdf(&wo, &h);
+        if wi.z > 0. {
+            fresnel * pdf_h / (4. * wo.dot(&h))
+        } else {
+            let denominator = wo.dot(&h) + eta * wi.dot(&h);
+            (1. - fresnel) * pdf_h * eta * eta * wi.dot(&h).abs() / (denominator * denominator)
+        }
+    }
+}
+
+/// Diffuse substrate under a dielectric coat, like a varnish or a plastic.
+/// The coat reflects as a smooth or rough dielectric, and lets the rest of
+/// the light through to the substrate. Between the two, the light bounces
+/// back and forth, reflected inside by the coat, until it leaves or is
+/// absorbed.
+#[derive(Debug, Clone)]
+pub struct Plastic {
+    pub albedo: Arc<dyn Texture>,
+    pub index_of_refraction: f32,
+    pub roughness: f32,
+    // Part of the light scattered by the substrate reflected back by the coat
+    internal_reflectance: f32,
+}
+
+impl Plastic {
+    pub fn new(albedo: Color, index_of_refraction: f32, roughness: f32) -> Plastic {
+        Plastic::textured(Arc::new(SolidColor::new(albedo)), index_of_refraction, roughness)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, index_of_refraction: f32, roughness: f32) -> Plastic {
+        let internal_reflectance = diffuse_fresnel(1. / index_of_refraction);
+        Plastic { albedo, index_of_refraction, roughness, internal_reflectance }
+    }
+
+    // Chances of sampling the coat rather than the substrate, as much as
+    // their contributions from `wo`
+    fn specular_probability(&self, albedo: &Color, wo: &Vector3<f32>) -> f32 {
+        let fresnel = fresnel_dielectric(wo.z, self.index_of_refraction);
+        let diffuse = (1. - fresnel) * albedo.luminance();
+        if fresnel + diffuse <= 0. {
+            return 1.
+        }
+        fresnel / (fresnel + diffuse)
+    }
+
+    // The light that enters and leaves through the coat, summed over the
+    // bounces between the substrate and the coat. The radiance spreads over
+    // a wider solid angle when leaving, hence the division by eta^2
+    fn diffuse(&self, albedo: &Color, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Color {
+        let eta = self.index_of_refraction;
+        let transmitted = (1. - fresnel_dielectric(wo.z, eta)) * (1. - fresnel_dielectric(wi.z, eta));
+        let bounces = albedo.map(|a| a / (1. - a * self.internal_reflectance));
+        bounces.scale(transmitted * wi.z / (eta * eta * std::f32::consts::PI))
+    }
+
+    fn local(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>, Ggx) {
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        let wi = onb.to_local(&direction.normalize());
+        (wo, wi, Ggx::from_roughness(self.roughness))
+    }
+}
+
+impl Scatterable for Plastic {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0. {
+            return None
+        }
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.position);
+        let specular_probability = self.specular_probability(&albedo, &wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+
+        let direction = if random::rng().gen::<f32>() < specular_probability {
+            if ggx.is_smooth() {
+                // Only the substrate can be evaluated, the coat is a mirror
+                let direction = onb.local(&Vector3::new(-wo.x, -wo.y, wo.z));
+                let fresnel = fresnel_dielectric(wo.z, self.index_of_refraction);
+                let scattered = Ray::with_time(hit_record.position, direction, ray.time);
+                return Some(ScatterRecord::specular(scattered, WHITE.scale(fresnel / specular_probability)))
+            }
+            let h = ggx.sample_visible_normal(&wo);
+            onb.local(&Vector3::reflect(&-wo, &h))
+        } else {
+            onb.local(&Vector3::random_cosine_direction())
+        };
+
+        let scattered = Ray::with_time(hit_record.position, direction, ray.time);
+        let pdf = self.pdf(ray, hit_record, &direction);
+        if pdf <= 0. {
+            return None
+        }
+        let bsdf = self.eval(ray, hit_record, &direction);
+        Some(ScatterRecord::new(scattered, bsdf, pdf))
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> Color {
+        let (wo, wi, ggx) = self.local(ray, hit_record, direction);
+        if wo.z <= 0. || wi.z <= 0. {
+            return BLACK
+        }
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.position);
+        let diffuse = self.diffuse(&albedo, &wo, &wi);
+        if ggx.is_smooth() {
+            // The lights are only sampled when the substrate is, its
+            // contribution is divided by the chances of sampling it
+            return diffuse.scale(1. / (1. - self.specular_probability(&albedo, &wo)))
+        }
+        let h = (wo + wi).normalize();
+        let fresnel = fresnel_dielectric(wo.dot(&h), self.index_of_refraction);
+        diffuse + WHITE.scale(fresnel * ggx.d(&h) * ggx.g(&wo, &wi) / (4. * wo.z))
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> f32 {
+        let (wo, wi, ggx) = self.local(ray, hit_record, direction);
+        if wo.z <= 0. || wi.z <= 0. {
+            return 0.
+        }
+        // With a smooth coat, the density once the substrate is picked
+        if ggx.is_smooth() {
+            return wi.z / std::f32::consts::PI
+        }
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.position);
+        let specular_probability = self.specular_probability(&albedo, &wo);
+        let diffuse = (1. - specular_probability) * wi.z / std::f32::consts::PI;
+        let h = (wo + wi).normalize();
+        diffuse + specular_probability * ggx.visible_normal_pdf(&wo, &h) / (4. * wo.dot(&h))
+    }
+}
+
+#[test]
+fn test_rough_dielectric() {
+    // The densities of reflection and refraction add up to the chances of
+    // sampling a direction, on both sides of the surface
+    let glass = Material::RoughDielectric(RoughDielectric::new(1.5, 0.4));
+    for front_face in [true, false] {
+        let ray = Ray::new(Vector3::new(-0.5, 0., 1.), Vector3::new(0.5, 0., -1.));
+        let hit_record = HitRecord {
+            position: Vector3::zeros(),
+            normal: Vector3::new(0., 0., 1.),
+            front_face,
+            t: 1.,
+            u: 0.,
+            v: 0.,
+            material: &glass,
+            incoming: ray.direction,
+        };
+
+        // Integrate over cells of equal solid angle, uniform in z and phi
+        let n = 300;
+        let mut integral = 0.;
+        for i in 0..n {
+            let z = -1. + (i as f32 + 0.5) * 2. / n as f32;
+            let r = (1. - z * z).sqrt();
+            for j in 0..n {
+                let phi = (j as f32 + 0.5) * 2. * std::f32::consts::PI / n as f32;
+                let direction = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+                integral += glass.pdf(&ray, &hit_record, &direction) as f64;
+            }
+        }
+        let integral = integral * 4. * std::f64::consts::PI / (n * n) as f64;
+
+        let samples = 20_000;
+        let mut sampled = 0;
+        for _ in 0..samples {
+            if let Some(scatter) = glass.scatter(&ray, &hit_record) {
+                // Weighted by G / G1 at most
+                let attenuation = scatter.attenuation();
+                assert!(attenuation.r >= 0. && attenuation.r <= 1. + 1e-4, "{:?}", attenuation);
+                sampled += 1;
+            }
+        }
+        let sampled = sampled as f64 / samples as f64;
+        assert!((integral - sampled).abs() < 0.005, "{} {}", integral, sampled);
+    }
+}
+
+#[test]
+fn test_dielectric_absorption() {
+    // Half of the red is left after a distance of 2, whatever the way out
+    let glass = Material::Dielectric(Dielectric::new(1.5).with_transmittance(Color::new(0.5, 1., 1.), 2.));
+    let ray = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 0., 0.5));
+    for front_face in [false, true] {
+        let hit_record = HitRecord {
+            position: Vector3::new(0., 0., 2.),
+            normal: Vector3::new(0., 0., -1.),
+            front_face,
+            t: 4.,
+            u: 0.,
+            v: 0.,
+            material: &glass,
+            incoming: ray.direction,
+        };
+        let expected = if front_face { 1. } else { 0.5 };
+        for _ in 0..10 {
+            let attenuation = glass.scatter(&ray, &hit_record).unwrap().attenuation();
+            assert!((attenuation.r - expected).abs() < 1e-5 && attenuation.g == 1., "{:?}", attenuation);
+        }
+    }
+}
+
+#[test]
+fn test_plastic() {
+    // With a white substrate nothing is absorbed: whatever the coat does not
+    // reflect eventually leaves through it
+    let plastic = Material::Plastic(Plastic::new(WHITE, 1.5, 0.));
+    for z in [1., 0.5, 0.1] {
+        let ray = Ray::new(Vector3::new(-(1f32 - z * z).sqrt(), 0., z), Vector3::new((1f32 - z * z).sqrt(), 0., -z));
+        let hit_record = HitRecord {
+            position: Vector3::zeros(),
+            normal: Vector3::new(0., 0., 1.),
+            front_face: true,
+            t: 1.,
+            u: 0.,
+            v: 0.,
+            material: &plastic,
+            incoming: ray.direction,
+        };
+        let samples = 20_000;
+        let mut mean = 0f64;
+        for _ in 0..samples {
+            let scatter = plastic.scatter(&ray, &hit_record).unwrap();
+            mean += scatter.attenuation().r as f64 / samples as f64;
         }
+        assert!((mean - 1.).abs() < 0.02, "{} {}", z, mean);
     }
 }
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use nalgebra::{Vector2, Vector3};
+
+use crate::{ray::*, material::*, aabb::Aabb, bvh::Bvh};
+use crate::primitives::{Primitive, set_face_normal, intersect_triangle, PLANAR_PADDING};
+
+#[cfg(test)]
+use crate::{color::Color, parameters::{EPSILON, INF}};
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+/// Vertex buffers shared by all the triangles of a mesh. `normals` and `uvs`
+/// are either empty or hold one entry per position, and every triangle
+/// indexes the three buffers with the same index.
+#[derive(Debug)]
+pub struct MeshData {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub uvs: Vec<Vector2<f32>>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Material,
+}
+
+/// One face of a mesh, only used as a leaf of the mesh BVH.
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+}
+
+impl Primitive for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+        let (p0, p1, p2) = (&positions[i0], &positions[i1], &positions[i2]);
+        let (t, b1, b2) = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+
+        let outward_normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+        let (geometric_normal, front_face) = set_face_normal(ray, outward_normal);
+
+        let b0 = 1. - b1 - b2;
+        // Without texture coordinates, fall back on the barycentric ones
+        let (u, v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let uvs = &self.mesh.uvs;
+            let uv = b0 * uvs[i0] + b1 * uvs[i1] + b2 * uvs[i2];
+            (uv.x, uv.y)
+        };
+
+        let normal = if self.mesh.normals.is_empty() {
+            geometric_normal
+        } else {
+            let normals = &self.mesh.normals;
+            let shading_normal = (b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]).normalize();
+            // Keep the shading normal on the same side as the geometry
+            if shading_normal.dot(&geometric_normal) < 0. {
+                -shading_normal
+            } else {
+                shading_normal
+            }
+        };
+
+        Some(HitRecord {
+            position: ray.at(t),
+            normal,
+            front_face,
+            t,
+            u,
+            v,
+            material: &self.mesh.material,
+            incoming: ray.direction,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+        Aabb::from_points(positions[i0], positions[i1])
+            .enclose(&positions[i2])
+            .padded(PLANAR_PADDING)
+    }
+}
+
+/// Indexed triangle mesh with its own BVH over its faces.
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: Bvh<'static>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vector3<f32>>,
+        normals: Vec<Vector3<f32>>,
+        uvs: Vec<Vector2<f32>>,
+        indices: Vec<[usize; 3]>,
+        material: Material,
+        ) -> TriangleMesh {
+        assert!(normals.is_empty() || normals.len() == positions.len(),
+            "a mesh needs either no normals or one per vertex");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(),
+            "a mesh needs either no uvs or one per vertex");
+        assert!(indices.iter().flatten().all(|&i| i < positions.len()),
+            "mesh index out of bounds");
+
+        let n_triangles = indices.len();
+        let data = Arc::new(MeshData { positions, normals, uvs, indices, material });
+        let triangles = (0..n_triangles)
+            .map(|index| Box::new(MeshTriangle { mesh: Arc::clone(&data), index }) as Box<dyn Primitive>)
+            .collect();
+
+        TriangleMesh { data, bvh: Bvh::new(triangles) }
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+}
+
+impl Primitive for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+#[test]
+fn test_mesh_interpolated_normal() {
+    // Unit quad in the z = 0 plane, with normals bent towards +x on the right
+    let material = Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let tilted = Vector3::new(1., 0., 1.).normalize();
+    let mesh = TriangleMesh::new(
+        vec![
+            Vector3::new(0., 0., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(1., 1., 0.),
+            Vector3::new(0., 1., 0.),
+        ],
+        vec![Vector3::new(0., 0., 1.), tilted, tilted, Vector3::new(0., 0., 1.)],
+        vec![],
+        vec![[0, 1, 2], [0, 2, 3]],
+        material);
+
+    let ray = Ray::new(Vector3::new(0.5, 0.25, 1.), Vector3::new(0., 0., -1.));
+    let hit = mesh.hit(&ray, EPSILON, INF).unwrap();
+    assert_approx_eq!(hit.t, 1.);
+    assert!(hit.front_face);
+    let expected = (Vector3::new(0., 0., 1.) * 0.5 + tilted * 0.5).normalize();
+    assert_approx_eq!(hit.normal.x, expected.x);
+    assert_approx_eq!(hit.normal.z, expected.z);
+
+    let miss = Ray::new(Vector3::new(1.5, 0.5, 1.), Vector3::new(0., 0., -1.));
+    assert!(mesh.hit(&miss, EPSILON, INF).is_none());
+}
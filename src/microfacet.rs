@@ -0,0 +1,191 @@
+use std::f32::consts::PI;
+use nalgebra::Vector3;
+use rand::Rng;
+
+use crate::{color::Color, random};
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+/// Isotropic Trowbridge-Reitz (GGX) distribution of microfacet normals,
+/// with Smith masking and shadowing. Directions are in the local frame of
+/// the surface, where the normal is Z.
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    pub alpha: f32,
+}
+
+impl Ggx {
+    /// Perceptually linear roughness in [0, 1], squared into `alpha`.
+    pub fn from_roughness(roughness: f32) -> Ggx {
+        Ggx { alpha: roughness * roughness }
+    }
+
+    /// Too smooth to be sampled other than as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < 1e-3
+    }
+
+    /// Density of microfacets with normal `h`, per unit of projected area.
+    pub fn d(&self, h: &Vector3<f32>) -> f32 {
+        if h.z <= 0. {
+            return 0.
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.) + 1.;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, w: &Vector3<f32>) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0. {
+            return f32::INFINITY
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.) / 2.
+    }
+
+    /// Fraction of the microfacets visible from `w`.
+    pub fn g1(&self, w: &Vector3<f32>) -> f32 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Height correlated fraction of the microfacets visible from both
+    /// directions.
+    pub fn g(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Microfacet normal seen from `wo`, following the distribution of
+    /// visible normals (Heitz 2018). `wo` must be above the surface.
+    pub fn sample_visible_normal(&self, wo: &Vector3<f32>) -> Vector3<f32> {
+        let mut rng = random::rng();
+        let (u1, u2): (f32, f32) = (rng.gen(), rng.gen());
+
+        // Stretch the view so that the distribution becomes a hemisphere
+        let v = Vector3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+        let length2 = v.x * v.x + v.y * v.y;
+        let t1 = if length2 > 0. {
+            Vector3::new(-v.y, v.x, 0.) / length2.sqrt()
+        } else {
+            Vector3::new(1., 0., 0.)
+        };
+        let t2 = v.cross(&t1);
+
+        // Uniform point of the projected half disk
+        let r = u1.sqrt();
+        let phi = 2. * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + v.z);
+        let p2 = (1. - s) * (1. - p1 * p1).max(0.).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * v;
+
+        // Back to the original roughness
+        Vector3::new(self.alpha * n.x, self.alpha * n.y, n.z.max(0.)).normalize()
+    }
+
+    /// Density of `sample_visible_normal` picking `h`, with respect to
+    /// solid angle.
+    pub fn visible_normal_pdf(&self, wo: &Vector3<f32>, h: &Vector3<f32>) -> f32 {
+        if wo.z <= 0. {
+            return 0.
+        }
+        self.g1(wo) * wo.dot(h).max(0.) * self.d(h) / wo.z
+    }
+}
+
+/// Unpolarized Fresnel reflectance of a conductor of complex index of
+/// refraction `eta + i k`, for the cosine of the incident angle.
+pub fn fresnel_conductor(cos_theta: f32, eta: &Color, k: &Color) -> Color {
+    let reflectance = |eta: f32, k: f32| {
+        let cos2 = cos_theta.clamp(0., 1.).powi(2);
+        let sin2 = 1. - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2. * cos_theta.clamp(0., 1.) * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Color::new(reflectance(eta.r, k.r), reflectance(eta.g, k.g), reflectance(eta.b, k.b))
+}
+
+/// Unpolarized Fresnel reflectance at the interface with a dielectric, for
+/// the cosine of the incident angle and the ratio `eta` of the index of
+/// refraction across the interface over the one on the incident side.
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let cos_i = cos_theta.clamp(0., 1.);
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        // Total internal reflection
+        return 1.
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Fraction of the light of a Lambertian distribution that the interface
+/// reflects, for the same ratio `eta` as `fresnel_dielectric`.
+pub fn diffuse_fresnel(eta: f32) -> f32 {
+    // 2 int_0^1 F(mu) mu dmu, by the midpoint rule
+    let n = 1000;
+    let sum: f32 = (0..n).map(|i| {
+        let mu = (i as f32 + 0.5) / n as f32;
+        fresnel_dielectric(mu, eta) * mu
+    }).sum();
+    2. * sum / n as f32
+}
+
+#[test]
+fn test_ggx() {
+    let ggx = Ggx::from_roughness(0.5);
+    let mut rng = random::rng();
+    let uniform = |rng: &mut random::Rng| {
+        // Uniform direction of the upper hemisphere, of density 1 / 2pi
+        let z: f32 = rng.gen();
+        let phi = 2. * PI * rng.gen::<f32>();
+        let r = (1. - z * z).sqrt();
+        Vector3::new(r * phi.cos(), r * phi.sin(), z)
+    };
+
+    // The projected microfacets cover the surface, and the visible ones
+    // its projection as seen from `wo`
+    let wo = Vector3::new(0.6, 0., 0.8);
+    let n = 200_000;
+    let (mut projected, mut visible) = (0., 0.);
+    for _ in 0..n {
+        let h = uniform(&mut rng);
+        projected += ggx.d(&h) * h.z * 2. * PI;
+        visible += ggx.visible_normal_pdf(&wo, &h) * 2. * PI;
+    }
+    assert!((projected / n as f32 - 1.).abs() < 0.02, "{}", projected / n as f32);
+    assert!((visible / n as f32 - 1.).abs() < 0.02, "{}", visible / n as f32);
+
+    for _ in 0..100 {
+        let h = ggx.sample_visible_normal(&wo);
+        assert_approx_eq!(h.norm(), 1.);
+        assert!(h.z >= 0. && h.dot(&wo) >= -1e-6);
+    }
+
+    // At normal incidence, ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+    let f = fresnel_conductor(1., &Color::new(0.2, 1., 1.5), &Color::new(3.9, 0., 2.));
+    assert_approx_eq!(f.r, (0.64 + 3.9 * 3.9) / (1.44 + 3.9 * 3.9));
+    assert_approx_eq!(f.g, 0.);
+    assert_approx_eq!(fresnel_conductor(0., &Color::new(0.2, 1., 1.5), &Color::new(3.9, 0., 2.)).b, 1.);
+
+    assert_approx_eq!(fresnel_dielectric(1., 1.5), 0.04);
+    assert_approx_eq!(fresnel_dielectric(0., 1.5), 1.);
+    // Beyond the critical angle of 41.8 degrees, leaving glass
+    assert_eq!(fresnel_dielectric(45f32.to_radians().cos(), 1. / 1.5), 1.);
+    assert!(fresnel_dielectric(30f32.to_radians().cos(), 1. / 1.5) < 0.1);
+
+    // From inside, everything beyond the critical angle comes back
+    assert!((diffuse_fresnel(1.5) - 0.092).abs() < 1e-3);
+    assert!((diffuse_fresnel(1. / 1.5) - (1. - (1. - 0.092) / 2.25)).abs() < 1e-3);
+}
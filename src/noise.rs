@@ -0,0 +1,181 @@
+use nalgebra::Vector3;
+use rand::{prelude::*, rngs::StdRng};
+
+#[cfg(test)]
+use crate::vector3::CustomVector3;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise, as in Ken Perlin's improved noise: random unit gradients
+/// at the lattice points, blended with a Hermite smoothstep.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    gradients: Vec<Vector3<f32>>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| random_unit_vector(&mut rng))
+            .collect();
+
+        Perlin {
+            gradients,
+            perm_x: permutation(&mut rng),
+            perm_y: permutation(&mut rng),
+            perm_z: permutation(&mut rng),
+        }
+    }
+
+    /// Noise value at `p`, in [-1, 1].
+    pub fn noise(&self, p: &Vector3<f32>) -> f32 {
+        let floor = p.map(|x| x.floor());
+        let frac = p - floor;
+        let (i, j, k) = (floor.x as i64, floor.y as i64, floor.z as i64);
+
+        let mut corners = [[[Vector3::zeros(); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[wrap(i + di as i64)]
+                        ^ self.perm_y[wrap(j + dj as i64)]
+                        ^ self.perm_z[wrap(k + dk as i64)];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+        trilinear_interpolation(&corners, &frac)
+    }
+
+    /// Sum of `octaves` layers of noise, each one with twice the frequency and
+    /// half the amplitude of the previous. Always positive.
+    pub fn turbulence(&self, p: &Vector3<f32>, octaves: usize) -> f32 {
+        let mut accumulator = 0.;
+        let mut p = *p;
+        let mut weight = 1.;
+        for _ in 0..octaves {
+            accumulator += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.;
+        }
+        accumulator.abs()
+    }
+}
+
+fn wrap(i: i64) -> usize {
+    i.rem_euclid(POINT_COUNT as i64) as usize
+}
+
+fn random_unit_vector(rng: &mut StdRng) -> Vector3<f32> {
+    loop {
+        let p = Vector3::new(
+            rng.gen_range(-1. ..1.),
+            rng.gen_range(-1. ..1.),
+            rng.gen_range(-1. ..1.));
+        let norm_squared = p.norm_squared();
+        if norm_squared > 1e-6 && norm_squared < 1. {
+            return p.normalize()
+        }
+    }
+}
+
+fn permutation(rng: &mut StdRng) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    p.shuffle(rng);
+    p
+}
+
+fn trilinear_interpolation(corners: &[[[Vector3<f32>; 2]; 2]; 2], frac: &Vector3<f32>) -> f32 {
+    let smooth = frac.map(|x| x * x * (3. - 2. * x));
+    let mut accumulator = 0.;
+    for (i, plane) in corners.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, gradient) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f32, j as f32, k as f32);
+                let weight = frac - Vector3::new(fi, fj, fk);
+                accumulator += (fi * smooth.x + (1. - fi) * (1. - smooth.x))
+                    * (fj * smooth.y + (1. - fj) * (1. - smooth.y))
+                    * (fk * smooth.z + (1. - fk) * (1. - smooth.z))
+                    * gradient.dot(&weight);
+            }
+        }
+    }
+    accumulator
+}
+
+/// Cellular noise: one feature point is scattered in each unit cell of the
+/// lattice, and the noise is the distance to the closest one.
+#[derive(Debug, Clone)]
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Worley {
+        Worley { seed }
+    }
+
+    /// Distance from `p` to the closest feature point, in [0, sqrt(3)].
+    pub fn noise(&self, p: &Vector3<f32>) -> f32 {
+        let cell = p.map(|x| x.floor());
+        let mut closest = f32::MAX;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let neighbour = cell + Vector3::new(di as f32, dj as f32, dk as f32);
+                    let feature = neighbour + self.feature_point(&neighbour);
+                    closest = closest.min((feature - p).norm());
+                }
+            }
+        }
+        closest
+    }
+
+    // Offset in [0, 1)^3 of the feature point of a cell, hashed from its coordinates
+    fn feature_point(&self, cell: &Vector3<f32>) -> Vector3<f32> {
+        let mut h = self.seed;
+        for c in cell.iter() {
+            h = splitmix64(h ^ (*c as i64 as u64));
+        }
+        let unit = |bits: u64| (bits & 0xffffff) as f32 / (1 << 24) as f32;
+        Vector3::new(unit(h), unit(h >> 24), unit(splitmix64(h)))
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[test]
+fn test_perlin_deterministic() {
+    let p = Vector3::new(1.3, -2.7, 0.4);
+    assert_eq!(Perlin::new(42).noise(&p), Perlin::new(42).noise(&p));
+    assert_ne!(Perlin::new(42).noise(&p), Perlin::new(7).noise(&p));
+
+    let perlin = Perlin::new(42);
+    for _ in 0..100 {
+        let p = Vector3::random(-10., 10.);
+        assert!(perlin.noise(&p).abs() <= 1.);
+        assert!(perlin.turbulence(&p, 7) >= 0.);
+    }
+    // Gradient noise vanishes on the lattice
+    assert_eq!(perlin.noise(&Vector3::new(3., 1., -2.)), 0.);
+}
+
+#[test]
+fn test_worley_deterministic() {
+    let worley = Worley::new(42);
+    for _ in 0..100 {
+        let p = Vector3::random(-10., 10.);
+        let d = worley.noise(&p);
+        assert!((0. ..=3f32.sqrt()).contains(&d));
+        assert_eq!(d, Worley::new(42).noise(&p));
+    }
+}
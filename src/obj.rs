@@ -0,0 +1,403 @@
+use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}, sync::Arc};
+use nalgebra::{Vector2, Vector3};
+
+use crate::{material::*, color::*, mesh::TriangleMesh, texture::ImageTexture, image_io::ImageError};
+
+#[cfg(test)]
+use crate::{primitives::Primitive, ray::Ray, parameters::{EPSILON, INF}};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Texture(ImageError),
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ObjError::Texture(error) => write!(f, "{}", error),
+            ObjError::Parse { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Material as described in a MTL file, before being mapped to a `Material`.
+#[derive(Debug, Clone)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub kd: Color,
+    pub ks: Color,
+    pub ke: Color,
+    pub ns: f32,
+    pub ni: f32,
+    pub d: f32,
+    pub map_kd: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> MtlMaterial {
+        MtlMaterial {
+            name: name.to_string(),
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: BLACK,
+            ke: BLACK,
+            ns: 0.,
+            ni: 1.,
+            d: 1.,
+            map_kd: None,
+        }
+    }
+
+    /// Pick the closest material the renderer knows about, loading the
+    /// diffuse texture if there is one.
+    pub fn to_material(&self) -> Result<Material, ImageError> {
+        let material = if max_component(&self.ke) > 0. {
+            Material::Light(Light::new(self.ke))
+        } else if self.d < 1. {
+            Material::Dielectric(Dielectric::new(self.ni))
+        } else if max_component(&self.ks) > max_component(&self.kd) {
+            // Map the Phong exponent to a fuzz, high exponents are sharp mirrors
+            let fuzz = (2. / (self.ns + 2.)).sqrt().min(1.);
+            Material::Metal(Metal::new(self.ks, fuzz))
+        } else if let Some(path) = &self.map_kd {
+            Material::Lambertian(Lambertian::textured(Arc::new(ImageTexture::load(path)?)))
+        } else {
+            Material::Lambertian(Lambertian::new(self.kd))
+        };
+        Ok(material)
+    }
+}
+
+fn max_component(color: &Color) -> f32 {
+    color.r.max(color.g).max(color.b)
+}
+
+/// Group of faces sharing a name and a material.
+pub struct ObjObject {
+    pub name: String,
+    pub mesh: TriangleMesh,
+}
+
+struct Parser<'a> {
+    file: &'a str,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: String) -> Result<T, ObjError> {
+        Err(ObjError::Parse { file: self.file.to_string(), line: self.line, message })
+    }
+
+    fn floats<const N: usize>(&self, keyword: &str, args: &[&str]) -> Result<[f32; N], ObjError> {
+        if args.len() < N {
+            return self.error(format!("`{}` expects {} numbers, got {}", keyword, N, args.len()))
+        }
+        let mut values = [0.; N];
+        for (value, arg) in values.iter_mut().zip(args) {
+            *value = match arg.parse() {
+                Ok(v) => v,
+                Err(_) => return self.error(format!("invalid number `{}` in `{}`", arg, keyword)),
+            };
+        }
+        Ok(values)
+    }
+
+    fn color(&self, keyword: &str, args: &[&str]) -> Result<Color, ObjError> {
+        // A single value is a grey
+        if args.len() == 1 {
+            let [v] = self.floats::<1>(keyword, args)?;
+            return Ok(Color::new(v, v, v))
+        }
+        let [r, g, b] = self.floats::<3>(keyword, args)?;
+        Ok(Color::new(r, g, b))
+    }
+
+    // OBJ indices start at 1, negative ones count back from the last element
+    fn index(&self, token: &str, len: usize) -> Result<usize, ObjError> {
+        let index: i64 = match token.parse() {
+            Ok(i) => i,
+            Err(_) => return self.error(format!("invalid index `{}`", token)),
+        };
+        let resolved = if index > 0 { index - 1 } else { len as i64 + index };
+        if index == 0 || resolved < 0 || resolved >= len as i64 {
+            return self.error(format!("index {} out of range ({} elements)", index, len))
+        }
+        Ok(resolved as usize)
+    }
+}
+
+/// Parse the content of a MTL file. `directory` is used to resolve texture paths.
+pub fn parse_mtl(source: &str, file: &str, directory: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+    let mut parser = Parser { file, line: 0 };
+
+    for (n, line) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let tokens: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+        let Some((&keyword, args)) = tokens.split_first() else { continue };
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return parser.error("`newmtl` without a name".to_string())
+            }
+            if let Some(material) = current.take() {
+                materials.insert(material.name.clone(), material);
+            }
+            current = Some(MtlMaterial::new(&args.join(" ")));
+            continue
+        }
+
+        let Some(material) = current.as_mut() else {
+            return parser.error(format!("`{}` before any `newmtl`", keyword))
+        };
+        match keyword {
+            "Kd" => material.kd = parser.color(keyword, args)?,
+            "Ks" => material.ks = parser.color(keyword, args)?,
+            "Ke" => material.ke = parser.color(keyword, args)?,
+            "Ns" => material.ns = parser.floats::<1>(keyword, args)?[0],
+            "Ni" => material.ni = parser.floats::<1>(keyword, args)?[0],
+            "d" => material.d = parser.floats::<1>(keyword, args)?[0],
+            "Tr" => material.d = 1. - parser.floats::<1>(keyword, args)?[0],
+            "map_Kd" => {
+                // Options such as `-s 1 1 1` come first, the file name is last
+                match args.last() {
+                    Some(name) => material.map_kd = Some(directory.join(name)),
+                    None => return parser.error("`map_Kd` without a file name".to_string()),
+                }
+            }
+            // Illumination model, ambient color and other maps are not supported
+            _ => {}
+        }
+    }
+
+    if let Some(material) = current.take() {
+        materials.insert(material.name.clone(), material);
+    }
+    Ok(materials)
+}
+
+// Faces of the group being parsed, with vertices deduplicated on their
+// (position, uv, normal) index triplet.
+struct MeshBuilder {
+    name: String,
+    material: Material,
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    positions: Vec<Vector3<f32>>,
+    normals: Vec<Option<Vector3<f32>>>,
+    uvs: Vec<Option<Vector2<f32>>>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl MeshBuilder {
+    fn new(name: String, material: Material) -> MeshBuilder {
+        MeshBuilder {
+            name,
+            material,
+            vertices: HashMap::new(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn build(self) -> Option<ObjObject> {
+        if self.indices.is_empty() {
+            return None
+        }
+        // A mesh has normals and uvs for all its vertices or for none of them
+        let normals = if self.normals.iter().all(Option::is_some) {
+            self.normals.into_iter().flatten().collect()
+        } else {
+            Vec::new()
+        };
+        let uvs = if self.uvs.iter().any(Option::is_some) {
+            self.uvs.into_iter().map(|uv| uv.unwrap_or(Vector2::new(0., 0.))).collect()
+        } else {
+            Vec::new()
+        };
+        Some(ObjObject {
+            name: self.name,
+            mesh: TriangleMesh::new(self.positions, normals, uvs, self.indices, self.material),
+        })
+    }
+}
+
+/// Parse the content of an OBJ file. Faces are fan triangulated and split in
+/// one mesh per group and material. `materials` holds the MTL definitions
+/// and `default_material` is used for faces without `usemtl`.
+pub fn parse_obj(
+    source: &str,
+    file: &str,
+    materials: &HashMap<String, MtlMaterial>,
+    default_material: Material,
+    ) -> Result<Vec<ObjObject>, ObjError> {
+    let mut parser = Parser { file, line: 0 };
+    // Converted materials, so that textures are only loaded once
+    let mut converted: HashMap<String, Material> = HashMap::new();
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut objects = Vec::new();
+
+    let mut group = "default".to_string();
+    let mut material = default_material.clone();
+    let mut builder = MeshBuilder::new(group.clone(), material.clone());
+
+    for (n, line) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let tokens: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+        let Some((&keyword, args)) = tokens.split_first() else { continue };
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parser.floats::<3>(keyword, args)?;
+                positions.push(Vector3::new(x, y, z));
+            }
+            "vt" => {
+                // The optional third coordinate is ignored
+                let u = parser.floats::<1>(keyword, args)?[0];
+                let v = if args.len() > 1 { parser.floats::<2>(keyword, args)?[1] } else { 0. };
+                uvs.push(Vector2::new(u, v));
+            }
+            "vn" => {
+                let [x, y, z] = parser.floats::<3>(keyword, args)?;
+                normals.push(Vector3::new(x, y, z).normalize());
+            }
+            "g" | "o" | "usemtl" => {
+                if keyword == "usemtl" {
+                    let name = args.join(" ");
+                    material = match (converted.get(&name), materials.get(&name)) {
+                        (Some(material), _) => material.clone(),
+                        (None, Some(mtl)) => {
+                            let material = mtl.to_material().map_err(ObjError::Texture)?;
+                            converted.insert(name, material.clone());
+                            material
+                        }
+                        (None, None) => return parser.error(format!("unknown material `{}`", name)),
+                    };
+                } else {
+                    group = if args.is_empty() { "default".to_string() } else { args.join(" ") };
+                }
+                let previous = std::mem::replace(&mut builder, MeshBuilder::new(group.clone(), material.clone()));
+                objects.extend(previous.build());
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return parser.error(format!("face with {} vertices", args.len()))
+                }
+                let mut face = Vec::with_capacity(args.len());
+                for arg in args {
+                    let mut parts = arg.split('/');
+                    let v = parser.index(parts.next().unwrap(), positions.len())?;
+                    let vt = match parts.next() {
+                        Some(token) if !token.is_empty() => Some(parser.index(token, uvs.len())?),
+                        _ => None,
+                    };
+                    let vn = match parts.next() {
+                        Some(token) if !token.is_empty() => Some(parser.index(token, normals.len())?),
+                        _ => None,
+                    };
+
+                    let next_index = builder.positions.len();
+                    let index = *builder.vertices.entry((v, vt, vn)).or_insert(next_index);
+                    if index == next_index {
+                        builder.positions.push(positions[v]);
+                        builder.uvs.push(vt.map(|i| uvs[i]));
+                        builder.normals.push(vn.map(|i| normals[i]));
+                    }
+                    face.push(index);
+                }
+                for i in 1..face.len() - 1 {
+                    builder.indices.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            // Smoothing groups, lines, points, mtllib (handled by the caller)...
+            _ => {}
+        }
+    }
+
+    objects.extend(builder.build());
+    Ok(objects)
+}
+
+/// Load an OBJ file along with the MTL libraries it references.
+pub fn load_obj<P: AsRef<Path>>(path: P, default_material: Material) -> Result<Vec<ObjObject>, ObjError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut materials = HashMap::new();
+    for line in source.lines() {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() == Some("mtllib") {
+            for name in tokens {
+                let mtl_path = directory.join(name);
+                let mtl_source = fs::read_to_string(&mtl_path)
+                    .map_err(|e| ObjError::Io(mtl_path.clone(), e))?;
+                let mtl_directory = mtl_path.parent().unwrap_or(Path::new(""));
+                materials.extend(parse_mtl(&mtl_source, &mtl_path.display().to_string(), mtl_directory)?);
+            }
+        }
+    }
+
+    parse_obj(&source, &path.display().to_string(), &materials, default_material)
+}
+
+#[test]
+fn test_parse_obj() {
+    let mtl = "
+newmtl red
+Kd 0.8 0.1 0.1
+newmtl lamp
+Ke 4 4 4
+";
+    let obj = "
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+g quad
+usemtl red
+f 1//1 2//1 3//1 4//1
+g lamp
+usemtl lamp
+f -4 -3 -2
+";
+    let materials = parse_mtl(mtl, "scene.mtl", Path::new("")).unwrap();
+    let default = Material::Lambertian(Lambertian::new(WHITE));
+    let objects = parse_obj(obj, "scene.obj", &materials, default).unwrap();
+
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects[0].name, "quad");
+    assert_eq!(objects[0].mesh.data().indices.len(), 2);
+    assert_eq!(objects[0].mesh.data().positions.len(), 4);
+    assert_eq!(objects[1].mesh.data().indices, vec![[0, 1, 2]]);
+    assert!(matches!(objects[1].mesh.data().material, Material::Light(_)));
+
+    let ray = Ray::new(Vector3::new(0.75, 0.5, 1.), Vector3::new(0., 0., -1.));
+    assert!(objects[0].mesh.hit(&ray, EPSILON, INF).is_some());
+}
+
+#[test]
+fn test_parse_obj_errors() {
+    let materials = HashMap::new();
+    let default = Material::Lambertian(Lambertian::new(WHITE));
+
+    let error = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n", "bad.obj", &materials, default.clone());
+    assert_eq!(error.err().unwrap().to_string(), "bad.obj:3: index 3 out of range (2 elements)");
+
+    let error = parse_obj("v 0 zero 0\n", "bad.obj", &materials, default);
+    assert_eq!(error.err().unwrap().to_string(), "bad.obj:1: invalid number `zero` in `v`");
+}
@@ -1,5 +1,5 @@
 use nalgebra::Vector3;
 
 pub const EPSILON: f32 = 1e-5;
-pub const INF: f32 = std::f32::MAX;
+pub const INF: f32 = f32::MAX;
 pub const ORIGIN: Vector3<f32> = Vector3::new(0., 0., 0.);
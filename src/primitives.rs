@@ -1,12 +1,80 @@
+use std::f32::consts::PI;
 use nalgebra::Vector3;
-use crate::{ray::*, material::*};
+use rand::prelude::*;
+use crate::{ray::*, material::*, aabb::Aabb, bvh::Bvh, parameters::*, vector3::Onb, random};
 
 
 pub trait Primitive : Send + Sync{
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    fn bounding_box(&self) -> Aabb;
+
+    /// Whether the primitive emits light and implements `sample` and `pdf`,
+    /// so that the renderer can aim at it.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Direction from `origin` to a random point of the primitive.
+    fn sample(&self, _origin: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(1., 0., 0.)
+    }
+
+    /// Density, with respect to solid angle, of `sample` picking `direction`.
+    fn pdf(&self, _origin: &Vector3<f32>, _direction: &Vector3<f32>) -> f32 {
+        0.
+    }
+}
+
+// The renderer borrows the primitives of a scene
+impl<T: Primitive + ?Sized> Primitive for &T {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
+
+    fn is_light(&self) -> bool {
+        (**self).is_light()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        (**self).sample(origin)
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        (**self).pdf(origin, direction)
+    }
+}
+
+/// Convert the density of a point picked uniformly on a surface of area
+/// `area` to a density over the directions seen from the ray origin.
+fn area_to_solid_angle_pdf(hit: &HitRecord, ray: &Ray, area: f32) -> f32 {
+    let distance_squared = hit.t * hit.t * ray.direction.norm_squared();
+    let cosine = (ray.direction.dot(&hit.normal) / ray.direction.norm()).abs();
+    if cosine < 1e-8 {
+        return 0.
+    }
+    distance_squared / (cosine * area)
+}
+
+// Same as `area_to_solid_angle_pdf`, for a planar primitive that may or may
+// not be hit by the direction
+fn planar_pdf<P: Primitive + ?Sized>(primitive: &P, origin: &Vector3<f32>, direction: &Vector3<f32>, area: f32) -> f32 {
+    let ray = Ray::new(*origin, *direction);
+    match primitive.hit(&ray, EPSILON, INF) {
+        Some(hit) => area_to_solid_angle_pdf(&hit, &ray, area),
+        None => 0.,
+    }
 }
 
-fn set_face_normal(ray: &Ray, outward_normal: Vector3<f32>) -> (Vector3<f32>, bool) {
+// Rectangles and triangles are infinitely thin, give their box some
+// thickness so that the slab test does not miss them.
+pub(crate) const PLANAR_PADDING: f32 = 1e-4;
+
+pub(crate) fn set_face_normal(ray: &Ray, outward_normal: Vector3<f32>) -> (Vector3<f32>, bool) {
     let front_face = ray.direction.dot(&outward_normal) < 0.;
     if front_face {
         (outward_normal, front_face)
@@ -15,6 +83,15 @@ fn set_face_normal(ray: &Ray, outward_normal: Vector3<f32>) -> (Vector3<f32>, bo
     }
 }
 
+/// Spherical coordinates of a point on the unit sphere, mapped to [0, 1]:
+/// `u` is the angle around the Y axis starting from -X, `v` goes from the
+/// south pole to the north pole.
+fn sphere_uv(p: &Vector3<f32>) -> (f32, f32) {
+    let theta = (-p.y).clamp(-1., 1.).acos();
+    let phi = (-p.z).atan2(p.x) + std::f32::consts::PI;
+    (phi / (2. * std::f32::consts::PI), theta / std::f32::consts::PI)
+}
+
 #[derive(Debug, Clone)]
 pub struct Sphere {
     pub center: Vector3<f32>,
@@ -30,38 +107,144 @@ impl Sphere {
 }
 
 impl Primitive for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.norm_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.norm_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-
-        if discriminant >= 0. {
-            let sqrtd = discriminant.sqrt();
-            let root_a = (-half_b - sqrtd) / a;
-            let root_b = (-half_b + sqrtd) / a;
-            for root in [root_a, root_b].iter() {
-                if *root < t_max && *root > t_min {
-                    let p = ray.at(*root);
-                    let outward_normal = (p - self.center) / self.radius;
-                    let (normal, front_face) = set_face_normal(ray, outward_normal);
-
-                    return Some(HitRecord { 
-                        position: p,
-                        normal,
-                        front_face,
-                        t: *root, 
-                        material: &self.material,
-                        incoming: ray.direction.clone(),
-                    });
-                }
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(&self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - r, self.center + r)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        let mut rng = random::rng();
+        let to_center = self.center - origin;
+        let distance_squared = to_center.norm_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // From inside, every direction reaches the sphere
+            return uniform_sphere_direction(rng.gen(), rng.gen())
+        }
+
+        // Uniform direction in the cone subtended by the sphere
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        let cos_theta = 1. + rng.gen::<f32>() * (cos_theta_max - 1.);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * rng.gen::<f32>();
+
+        Onb::from_w(&to_center).local(&Vector3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta))
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        let distance_squared = (self.center - origin).norm_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 1. / (4. * PI)
+        }
+        if self.hit(&Ray::new(*origin, *direction), EPSILON, INF).is_none() {
+            return 0.
+        }
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        1. / (2. * PI * (1. - cos_theta_max))
+    }
+}
+
+fn hit_sphere<'a>(
+    center: &Vector3<f32>,
+    radius: f32,
+    material: &'a Material,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+    ) -> Option<HitRecord<'a>> {
+    let oc = ray.origin - center;
+    let a = ray.direction.norm_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.norm_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+
+    if discriminant >= 0. {
+        let sqrtd = discriminant.sqrt();
+        let root_a = (-half_b - sqrtd) / a;
+        let root_b = (-half_b + sqrtd) / a;
+        for root in [root_a, root_b].iter() {
+            if *root < t_max && *root > t_min {
+                let p = ray.at(*root);
+                let outward_normal = (p - center) / radius;
+                let (normal, front_face) = set_face_normal(ray, outward_normal);
+                let (u, v) = sphere_uv(&outward_normal);
+
+                return Some(HitRecord { 
+                    position: p,
+                    normal,
+                    front_face,
+                    t: *root, 
+                    u,
+                    v,
+                    material,
+                    incoming: ray.direction,
+                });
             }
         }
-        None
     }
+    None
 }
 
+/// Sphere moving linearly from `center0` at `time0` to `center1` at `time1`.
+pub struct MovingSphere {
+    pub center0: Vector3<f32>,
+    pub center1: Vector3<f32>,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl MovingSphere {
+    pub fn new(center0: Vector3<f32>, center1: Vector3<f32>, time0: f32, time1: f32, radius: f32, material: Material) -> MovingSphere {
+        MovingSphere { center0, center1, time0, time1, radius, material }
+    }
+
+    pub fn center(&self, time: f32) -> Vector3<f32> {
+        self.center0 + (self.center1 - self.center0) * interpolation(time, self.time0, self.time1)
+    }
+}
+
+// Position of `time` between `time0` and `time1`, held at the ends
+fn interpolation(time: f32, time0: f32, time1: f32) -> f32 {
+    if time1 > time0 {
+        ((time - time0) / (time1 - time0)).clamp(0., 1.)
+    } else {
+        0.
+    }
+}
+
+// Moving primitives are not sampled as lights, their emission is only
+// found by the rays scattered towards them.
+impl Primitive for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(&self.center(ray.time), self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center0 - r, self.center0 + r)
+            .surrounding(&Aabb::from_points(self.center1 - r, self.center1 + r))
+    }
+}
+
+fn uniform_sphere_direction(r1: f32, r2: f32) -> Vector3<f32> {
+    let z = 1. - 2. * r1;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * r2;
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+
 #[derive(Debug, Clone)]
 pub struct RectangleXY {
     x0: f32,
@@ -79,7 +262,7 @@ impl RectangleXY {
 }
 
 impl Primitive for RectangleXY {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.k - ray.origin.z) / ray.direction.z;
         if t < t_min || t > t_max {
             return None
@@ -90,19 +273,47 @@ impl Primitive for RectangleXY {
         if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
             return None
         }
+        let u = (x - self.x0) / (self.x1 - self.x0);
+        let v = (y - self.y0) / (self.y1 - self.y0);
 
         let outward_normal = Vector3::new(0., 0., 1.);
         let (normal, front_face) = set_face_normal(ray, outward_normal);
 
-        return Some(HitRecord {
+        Some(HitRecord {
             position: ray.at(t),
             normal,
             front_face,
             t,
+            u,
+            v,
             material: &self.material,
-            incoming: ray.direction.clone(),
+            incoming: ray.direction,
         })
     }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Vector3::new(self.x0, self.y0, self.k - PLANAR_PADDING),
+            Vector3::new(self.x1, self.y1, self.k + PLANAR_PADDING))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        let mut rng = random::rng();
+        let point = Vector3::new(
+            rng.gen_range(self.x0..=self.x1),
+            rng.gen_range(self.y0..=self.y1),
+            self.k);
+        point - origin
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        planar_pdf(self, origin, direction, area)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -122,7 +333,7 @@ impl RectangleXZ {
 }
 
 impl Primitive for RectangleXZ {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.k - ray.origin.y) / ray.direction.y;
         if t < t_min || t > t_max {
             return None
@@ -133,19 +344,47 @@ impl Primitive for RectangleXZ {
         if x < self.x0 || x > self.x1 || z < self.z0 || z > self.z1 {
             return None
         }
+        let u = (x - self.x0) / (self.x1 - self.x0);
+        let v = (z - self.z0) / (self.z1 - self.z0);
 
         let outward_normal = Vector3::new(0., 1., 0.);
         let (normal, front_face) = set_face_normal(ray, outward_normal);
 
-        return Some(HitRecord {
+        Some(HitRecord {
             position: ray.at(t),
             normal,
             front_face,
             t,
+            u,
+            v,
             material: &self.material,
-            incoming: ray.direction.clone(),
+            incoming: ray.direction,
         })
     }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Vector3::new(self.x0, self.k - PLANAR_PADDING, self.z0),
+            Vector3::new(self.x1, self.k + PLANAR_PADDING, self.z1))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        let mut rng = random::rng();
+        let point = Vector3::new(
+            rng.gen_range(self.x0..=self.x1),
+            self.k,
+            rng.gen_range(self.z0..=self.z1));
+        point - origin
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        planar_pdf(self, origin, direction, area)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -165,7 +404,7 @@ impl RectangleYZ {
 }
 
 impl Primitive for RectangleYZ {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.k - ray.origin.x) / ray.direction.x;
         if t < t_min || t > t_max {
             return None
@@ -176,61 +415,182 @@ impl Primitive for RectangleYZ {
         if z < self.z0 || z > self.z1 || y < self.y0 || y > self.y1 {
             return None
         }
+        let u = (y - self.y0) / (self.y1 - self.y0);
+        let v = (z - self.z0) / (self.z1 - self.z0);
 
         let outward_normal = Vector3::new(1., 0., 0.);
         let (normal, front_face) = set_face_normal(ray, outward_normal);
 
-        return Some(HitRecord {
+        Some(HitRecord {
             position: ray.at(t),
             normal,
             front_face,
             t,
+            u,
+            v,
             material: &self.material,
-            incoming: ray.direction.clone(),
+            incoming: ray.direction,
         })
     }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Vector3::new(self.k - PLANAR_PADDING, self.y0, self.z0),
+            Vector3::new(self.k + PLANAR_PADDING, self.y1, self.z1))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        let mut rng = random::rng();
+        let point = Vector3::new(
+            self.k,
+            rng.gen_range(self.y0..=self.y1),
+            rng.gen_range(self.z0..=self.z1));
+        point - origin
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        planar_pdf(self, origin, direction, area)
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection, returns the distance along the
+/// ray and the barycentric coordinates of the hit relative to `p1` and `p2`.
+pub(crate) fn intersect_triangle(
+    ray: &Ray,
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    t_min: f32,
+    t_max: f32,
+    ) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = ray.direction.cross(&edge2);
+    let det = edge1.dot(&pvec);
+    if det.abs() < 1e-12 {
+        // The ray is parallel to the triangle
+        return None
+    }
+
+    let inv_det = 1. / det;
+    let tvec = ray.origin - p0;
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0. ..=1.).contains(&b1) {
+        return None
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let b2 = ray.direction.dot(&qvec) * inv_det;
+    if b2 < 0. || b1 + b2 > 1. {
+        return None
+    }
+
+    let t = edge2.dot(&qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None
+    }
+    Some((t, b1, b2))
+}
+
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    pub vertices: [Vector3<f32>; 3],
+    pub material: Material,
+}
+
+impl Triangle {
+    pub fn new(p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>, material: Material) -> Triangle {
+        Triangle { vertices: [p0, p1, p2], material }
+    }
+}
+
+impl Primitive for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let [p0, p1, p2] = &self.vertices;
+        let (t, u, v) = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+
+        let outward_normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+        let (normal, front_face) = set_face_normal(ray, outward_normal);
+
+        Some(HitRecord {
+            position: ray.at(t),
+            normal,
+            front_face,
+            t,
+            u,
+            v,
+            material: &self.material,
+            incoming: ray.direction,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [p0, p1, p2] = &self.vertices;
+        Aabb::from_points(*p0, *p1).enclose(p2).padded(PLANAR_PADDING)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        // Uniform point on the triangle, folding the unit square onto it
+        let mut rng = random::rng();
+        let [p0, p1, p2] = &self.vertices;
+        let (mut b1, mut b2) = (rng.gen::<f32>(), rng.gen::<f32>());
+        if b1 + b2 > 1. {
+            b1 = 1. - b1;
+            b2 = 1. - b2;
+        }
+        p0 + b1 * (p1 - p0) + b2 * (p2 - p0) - origin
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        let [p0, p1, p2] = &self.vertices;
+        let area = 0.5 * (p1 - p0).cross(&(p2 - p0)).norm();
+        planar_pdf(self, origin, direction, area)
+    }
 }
 
 pub struct RectangularCuboid { // "Box" is a reserved keyword lol
     pub vertice0: Vector3<f32>,
     pub vertice1: Vector3<f32>,
-    sides: Vec<Box<dyn Primitive>>,
+    sides: Bvh<'static>,
 }
 
 impl RectangularCuboid {
     pub fn new(p0: Vector3<f32>, p1: Vector3<f32>, material: Material) -> RectangularCuboid {
-        let mut sides = Vec::<Box<dyn Primitive>>::new();
+        let sides: Vec<Box<dyn Primitive>> = vec![
+            Box::new(RectangleXY::new(p0.x, p1.x, p0.y, p1.y, p1.z, material.clone())),
+            Box::new(RectangleXY::new(p0.x, p1.x, p0.y, p1.y, p0.z, material.clone())),
 
-        sides.push(Box::new(RectangleXY::new(p0.x, p1.x, p0.y, p1.y, p1.z, material.clone())));
-        sides.push(Box::new(RectangleXY::new(p0.x, p1.x, p0.y, p1.y, p0.z, material.clone())));
+            Box::new(RectangleXZ::new(p0.x, p1.x, p0.z, p1.z, p1.y, material.clone())),
+            Box::new(RectangleXZ::new(p0.x, p1.x, p0.z, p1.z, p0.y, material.clone())),
 
-        sides.push(Box::new(RectangleXZ::new(p0.x, p1.x, p0.z, p1.z, p1.y, material.clone())));
-        sides.push(Box::new(RectangleXZ::new(p0.x, p1.x, p0.z, p1.z, p0.y, material.clone())));
-
-        sides.push(Box::new(RectangleYZ::new(p0.y, p1.y, p0.z, p1.z, p1.x, material.clone())));
-        sides.push(Box::new(RectangleYZ::new(p0.y, p1.y, p0.z, p1.z, p0.x, material.clone())));
+            Box::new(RectangleYZ::new(p0.y, p1.y, p0.z, p1.z, p1.x, material.clone())),
+            Box::new(RectangleYZ::new(p0.y, p1.y, p0.z, p1.z, p0.x, material)),
+        ];
 
         RectangularCuboid {
             vertice0: p0,
             vertice1: p1,
-            sides
+            sides: Bvh::new(sides),
         }
     }
 }
 
 impl Primitive for RectangularCuboid {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        // It is the same function as hit_world
-        // except the cheeky &
-        let mut closest_so_far = t_max;
-        let mut hit_record = None;
-        for side in &self.sides {
-            if let Some(hit) = side.hit(ray, t_min, closest_so_far) {
-                closest_so_far = hit.t;
-                hit_record = Some(hit)
-            }
-        }
-        hit_record
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.sides.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.sides.bounding_box()
     }
 }
 
@@ -246,8 +606,8 @@ impl Translate {
 }
 
 impl Primitive for Translate {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let moved_ray = Ray::new(ray.origin - self.offset, ray.direction);
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let moved_ray = Ray::with_time(ray.origin - self.offset, ray.direction, ray.time);
         match self.hittable.hit(&moved_ray, t_min, t_max) {
             None => None,
             Some(hit) => {
@@ -257,12 +617,31 @@ impl Primitive for Translate {
                     normal,
                     front_face,
                     t: hit.t,
+                    u: hit.u,
+                    v: hit.v,
                     material: hit.material,
                     incoming: hit.incoming,
                 })
             }
         }
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.hittable.bounding_box();
+        Aabb::new(bbox.min + self.offset, bbox.max + self.offset)
+    }
+
+    fn is_light(&self) -> bool {
+        self.hittable.is_light()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        self.hittable.sample(&(origin - self.offset))
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        self.hittable.pdf(&(origin - self.offset), direction)
+    }
 }
 
 pub struct RotateY {
@@ -275,10 +654,24 @@ impl RotateY {
     pub fn new(angle: f32, hittable: Box<dyn Primitive>) -> RotateY {
         RotateY { sin_theta: angle.sin(), cos_theta: angle.cos(), hittable}
     }
+
+    fn to_object(&self, v: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(
+            self.cos_theta * v.x - self.sin_theta * v.z,
+            v.y,
+            self.sin_theta * v.x + self.cos_theta * v.z)
+    }
+
+    fn to_world(&self, v: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            - self.sin_theta * v.x + self.cos_theta * v.z)
+    }
 }
 
 impl Primitive for RotateY {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let origin = Vector3::new(
             self.cos_theta * ray.origin.x - self.sin_theta * ray.origin.z,
             ray.origin.y,
@@ -287,7 +680,7 @@ impl Primitive for RotateY {
             self.cos_theta * ray.direction.x - self.sin_theta * ray.direction.z,
             ray.direction.y,
             self.sin_theta * ray.direction.x + self.cos_theta * ray.direction.z);
-        let rotated_ray = Ray::new(origin, direction);
+        let rotated_ray = Ray::with_time(origin, direction, ray.time);
 
         match self.hittable.hit(&rotated_ray, t_min, t_max) {
             None => None,
@@ -306,10 +699,181 @@ impl Primitive for RotateY {
                     normal,
                     front_face,
                     t: hit.t,
+                    u: hit.u,
+                    v: hit.v,
                     material: hit.material,
                     incoming: hit.incoming,
                 })
             }
         }
     }
+
+    fn bounding_box(&self) -> Aabb {
+        // Rotate the eight corners of the inner box and take their extent
+        let bbox = self.hittable.bounding_box();
+        let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        for &x in [bbox.min.x, bbox.max.x].iter() {
+            for &y in [bbox.min.y, bbox.max.y].iter() {
+                for &z in [bbox.min.z, bbox.max.z].iter() {
+                    let corner = Vector3::new(
+                        self.cos_theta * x + self.sin_theta * z,
+                        y,
+                        - self.sin_theta * x + self.cos_theta * z);
+                    min = min.inf(&corner);
+                    max = max.sup(&corner);
+                }
+            }
+        }
+        Aabb::new(min, max)
+    }
+
+    fn is_light(&self) -> bool {
+        self.hittable.is_light()
+    }
+
+    fn sample(&self, origin: &Vector3<f32>) -> Vector3<f32> {
+        self.to_world(&self.hittable.sample(&self.to_object(origin)))
+    }
+
+    fn pdf(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+        self.hittable.pdf(&self.to_object(origin), &self.to_object(direction))
+    }
+}
+
+/// Pose of an animated primitive at one end of the shutter: a rotation
+/// around Y (in radians) followed by a translation.
+#[derive(Debug, Clone, Copy)]
+pub struct TransformKey {
+    pub angle: f32,
+    pub offset: Vector3<f32>,
+}
+
+impl TransformKey {
+    pub fn new(angle: f32, offset: Vector3<f32>) -> TransformKey {
+        TransformKey { angle, offset }
+    }
+}
+
+/// Primitive moving between two poses, interpolated linearly in time.
+pub struct AnimatedTransform {
+    hittable: Box<dyn Primitive>,
+    key0: TransformKey,
+    key1: TransformKey,
+    time0: f32,
+    time1: f32,
+}
+
+impl AnimatedTransform {
+    pub fn new(hittable: Box<dyn Primitive>, key0: TransformKey, key1: TransformKey, time0: f32, time1: f32) -> AnimatedTransform {
+        AnimatedTransform { hittable, key0, key1, time0, time1 }
+    }
+
+    fn key(&self, time: f32) -> TransformKey {
+        let s = interpolation(time, self.time0, self.time1);
+        TransformKey {
+            angle: self.key0.angle + (self.key1.angle - self.key0.angle) * s,
+            offset: self.key0.offset + (self.key1.offset - self.key0.offset) * s,
+        }
+    }
+}
+
+// Same conventions as `RotateY`
+fn rotate_y(v: &Vector3<f32>, angle: f32) -> Vector3<f32> {
+    let (sin_theta, cos_theta) = angle.sin_cos();
+    Vector3::new(
+        cos_theta * v.x + sin_theta * v.z,
+        v.y,
+        - sin_theta * v.x + cos_theta * v.z)
+}
+
+impl Primitive for AnimatedTransform {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let key = self.key(ray.time);
+        let object_ray = Ray::with_time(
+            rotate_y(&(ray.origin - key.offset), -key.angle),
+            rotate_y(&ray.direction, -key.angle),
+            ray.time);
+
+        let hit = self.hittable.hit(&object_ray, t_min, t_max)?;
+        let outward_normal = rotate_y(&hit.normal, key.angle);
+        // The inner normal faces the object ray, flip it back if needed
+        let outward_normal = if hit.front_face { outward_normal } else { -outward_normal };
+        let (normal, front_face) = set_face_normal(ray, outward_normal);
+        Some(HitRecord {
+            position: rotate_y(&hit.position, key.angle) + key.offset,
+            normal,
+            front_face,
+            t: hit.t,
+            u: hit.u,
+            v: hit.v,
+            material: hit.material,
+            incoming: ray.direction,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.hittable.bounding_box();
+        let (offset_min, offset_max) = (self.key0.offset.inf(&self.key1.offset), self.key0.offset.sup(&self.key1.offset));
+        if self.key0.angle == self.key1.angle {
+            // Translations are linear, the box moves between its two ends
+            let corners: Vec<Vector3<f32>> = (0..8)
+                .map(|i| Vector3::new(
+                    if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+                    if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+                    if i & 4 == 0 { bbox.min.z } else { bbox.max.z }))
+                .map(|corner| rotate_y(&corner, self.key0.angle))
+                .collect();
+            let min = corners.iter().fold(corners[0], |a, b| a.inf(b));
+            let max = corners.iter().fold(corners[0], |a, b| a.sup(b));
+            return Aabb::new(min + offset_min, max + offset_max)
+        }
+        // While turning, the box stays within the cylinder around Y
+        // containing its corners
+        let radius = [bbox.min.x, bbox.max.x].iter()
+            .flat_map(|&x| [bbox.min.z, bbox.max.z].map(|z| (x * x + z * z).sqrt()))
+            .fold(0f32, f32::max);
+        Aabb::new(
+            Vector3::new(-radius, bbox.min.y, -radius) + offset_min,
+            Vector3::new(radius, bbox.max.y, radius) + offset_max)
+    }
+}
+
+#[test]
+fn test_motion() {
+    let material = Material::Lambertian(Lambertian::new(crate::color::WHITE));
+    let sphere = MovingSphere::new(Vector3::new(0., 0., -5.), Vector3::new(2., 0., -5.), 0., 1., 0.5, material.clone());
+    let ray = |x: f32, time: f32| Ray::with_time(Vector3::new(x, 0., 0.), Vector3::new(0., 0., -1.), time);
+    assert!(sphere.hit(&ray(0., 0.), EPSILON, INF).is_some());
+    assert!(sphere.hit(&ray(0., 1.), EPSILON, INF).is_none());
+    assert!((sphere.hit(&ray(1., 0.5), EPSILON, INF).unwrap().t - 4.5).abs() < 1e-5);
+    let bbox = sphere.bounding_box();
+    assert!((bbox.min.x + 0.5).abs() < 1e-6 && (bbox.max.x - 2.5).abs() < 1e-6);
+
+    // A unit cube turning by a quarter turn while moving down the Z axis
+    let cube = RectangularCuboid::new(Vector3::new(0., 0., 0.), Vector3::new(1., 1., 1.), material);
+    let animated = AnimatedTransform::new(
+        Box::new(cube),
+        TransformKey::new(0., Vector3::new(0., 0., -5.)),
+        TransformKey::new(PI / 2., Vector3::new(0., 0., -10.)),
+        0., 1.);
+    let ray = |x: f32, time: f32| Ray::with_time(Vector3::new(x, 0.5, 0.), Vector3::new(0., 0., -1.), time);
+    let hit = animated.hit(&ray(0.5, 0.), EPSILON, INF).unwrap();
+    assert!((hit.position.z + 4.).abs() < 1e-4 && hit.normal.z > 0.99);
+    // Turned to lie in front of its pivot, as `RotateY` does
+    assert!(animated.hit(&ray(-0.5, 1.), EPSILON, INF).is_none());
+    let hit = animated.hit(&ray(0.5, 1.), EPSILON, INF).unwrap();
+    assert!((hit.position.z + 10.).abs() < 1e-4 && hit.normal.z > 0.99);
+    let cube = RectangularCuboid::new(Vector3::new(0., 0., 0.), Vector3::new(1., 1., 1.), Material::Lambertian(Lambertian::new(crate::color::WHITE)));
+    let fixed = Translate::new(Box::new(RotateY::new(PI / 2., Box::new(cube))), Vector3::new(0., 0., -10.));
+    let slanted = Ray::with_time(Vector3::new(3., 0.7, 0.), Vector3::new(-0.25, 0., -1.), 1.);
+    assert!((fixed.hit(&slanted, EPSILON, INF).unwrap().position - animated.hit(&slanted, EPSILON, INF).unwrap().position).norm() < 1e-4);
+
+    let bbox = animated.bounding_box();
+    for time in [0., 0.3, 0.7, 1.] {
+        let inner = AnimatedTransform::new(
+            Box::new(RectangularCuboid::new(Vector3::new(0., 0., 0.), Vector3::new(1., 1., 1.), Material::Lambertian(Lambertian::new(crate::color::WHITE)))),
+            animated.key(time), animated.key(time), 0., 1.).bounding_box();
+        assert!(bbox.min <= inner.min && inner.max <= bbox.max, "{:?} outside {:?}", inner, bbox);
+    }
 }
@@ -0,0 +1,275 @@
+use std::{f32::consts::PI, sync::Arc};
+use nalgebra::Vector3;
+use rand::Rng;
+
+use crate::{ray::*, color::*, vector3::*, texture::*, microfacet::Ggx, material::*, random};
+
+// Smoother microfacets are too peaked to be mixed with the other lobes
+const MIN_ROUGHNESS: f32 = 0.032;
+
+/// Principled BSDF after Disney (Burley 2012, 2015), with knobs in [0, 1]:
+/// a diffuse base with retro-reflection and sheen, a GGX specular layer
+/// turning into a metal with `metallic`, a clear coat on top, and a rough
+/// dielectric for `transmission`. The index of refraction follows from
+/// `specular`, 0.5 giving 1.5.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Reflectance at normal incidence of dielectrics, scaled to 8%.
+    pub specular: f32,
+    /// Tints the dielectric reflections towards the base color.
+    pub specular_tint: f32,
+    /// Grazing reflections of cloth.
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_gloss: f32,
+    pub transmission: f32,
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Principled {
+        Principled::textured(Arc::new(SolidColor::new(base_color)))
+    }
+
+    /// Plastic-like defaults: rough dielectric specular over the diffuse base.
+    pub fn textured(base_color: Arc<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: 0.,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.,
+            sheen: 0.,
+            sheen_tint: 0.5,
+            clearcoat: 0.,
+            clearcoat_gloss: 1.,
+            transmission: 0.,
+        }
+    }
+
+    fn ggx(&self) -> Ggx {
+        Ggx::from_roughness(self.roughness.max(MIN_ROUGHNESS))
+    }
+
+    fn clearcoat_ggx(&self) -> Ggx {
+        let alpha = 0.1 + (0.001 - 0.1) * self.clearcoat_gloss;
+        Ggx { alpha: alpha.max(MIN_ROUGHNESS * MIN_ROUGHNESS) }
+    }
+
+    fn dielectric(&self) -> RoughDielectric {
+        let r0 = (0.08 * self.specular).sqrt().min(0.99);
+        RoughDielectric::new((1. + r0) / (1. - r0), self.roughness.max(MIN_ROUGHNESS))
+    }
+
+    // Lobes weighted by the parameters: the diffuse base with its sheen,
+    // the specular layer, the clear coat and the transmission
+    fn weights(&self) -> [f32; 4] {
+        let transmission = (1. - self.metallic) * self.transmission;
+        [(1. - self.metallic) * (1. - self.transmission), 1. - transmission, 0.25 * self.clearcoat, transmission]
+    }
+
+    // Chances of sampling each lobe, roughly following their contributions
+    fn probabilities(&self, base: &Color, wo: &Vector3<f32>) -> [f32; 4] {
+        let [diffuse, specular, clearcoat, transmission] = self.weights();
+        // The specular layer keeps some chances, so that the reflections of
+        // glossy plastics converge
+        let specular = specular * schlick(&self.specular_color(base), wo.z).luminance().max(0.25);
+        let clearcoat = clearcoat * schlick(&Color::new(0.04, 0.04, 0.04), wo.z).r.max(0.25);
+        let total = diffuse + specular + clearcoat + transmission;
+        if total <= 0. {
+            return [0.; 4]
+        }
+        [diffuse / total, specular / total, clearcoat / total, transmission / total]
+    }
+
+    fn tint(base: &Color) -> Color {
+        let luminance = base.luminance();
+        if luminance > 0. { base.scale(1. / luminance) } else { WHITE }
+    }
+
+    // Reflectance at normal incidence of the specular layer
+    fn specular_color(&self, base: &Color) -> Color {
+        let dielectric = lerp(&WHITE, &Principled::tint(base), self.specular_tint).scale(0.08 * self.specular);
+        lerp(&dielectric, base, self.metallic)
+    }
+
+    fn local(ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        let onb = Onb::from_w(&hit_record.normal);
+        (onb.to_local(&-ray.direction.normalize()), onb.to_local(&direction.normalize()))
+    }
+}
+
+fn lerp(a: &Color, b: &Color, t: f32) -> Color {
+    a.scale(1. - t) + b.scale(t)
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1. - cosine.clamp(0., 1.)).powi(5)
+}
+
+fn schlick(r0: &Color, cosine: f32) -> Color {
+    lerp(r0, &WHITE, schlick_weight(cosine))
+}
+
+// GGX reflection times the cosine of `wi`, without the Fresnel term
+fn microfacet_reflection(ggx: &Ggx, wo: &Vector3<f32>, wi: &Vector3<f32>, h: &Vector3<f32>) -> f32 {
+    ggx.d(h) * ggx.g(wo, wi) / (4. * wo.z)
+}
+
+fn microfacet_pdf(ggx: &Ggx, wo: &Vector3<f32>, h: &Vector3<f32>) -> f32 {
+    ggx.visible_normal_pdf(wo, h) / (4. * wo.dot(h))
+}
+
+impl Scatterable for Principled {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        // Only the transmission brings rays inside
+        if !hit_record.front_face {
+            return self.dielectric().scatter(ray, hit_record)
+        }
+        let onb = Onb::from_w(&hit_record.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0. {
+            return None
+        }
+        let base = self.base_color.value(hit_record.u, hit_record.v, &hit_record.position);
+        let probabilities = self.probabilities(&base, &wo);
+
+        let mut choice = random::rng().gen::<f32>();
+        let lobe = probabilities.iter().position(|&p| {
+            choice -= p;
+            choice < 0.
+        }).unwrap_or(3);
+        let direction = match lobe {
+            0 => onb.local(&Vector3::random_cosine_direction()),
+            1 | 2 => {
+                let ggx = if lobe == 1 { self.ggx() } else { self.clearcoat_ggx() };
+                let h = ggx.sample_visible_normal(&wo);
+                onb.local(&Vector3::reflect(&-wo, &h))
+            }
+            _ => self.dielectric().scatter(ray, hit_record)?.ray.direction,
+        };
+
+        let pdf = self.pdf(ray, hit_record, &direction);
+        if pdf <= 0. {
+            return None
+        }
+        let bsdf = self.eval(ray, hit_record, &direction);
+        Some(ScatterRecord::new(Ray::with_time(hit_record.position, direction, ray.time), bsdf, pdf))
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> Color {
+        if !hit_record.front_face {
+            return self.dielectric().eval(ray, hit_record, direction)
+        }
+        let (wo, wi) = Principled::local(ray, hit_record, direction);
+        let base = self.base_color.value(hit_record.u, hit_record.v, &hit_record.position);
+        let [diffuse_weight, specular_weight, clearcoat_weight, transmission_weight] = self.weights();
+
+        let mut value = BLACK;
+        if wo.z > 0. && wi.z > 0. {
+            let h = (wo + wi).normalize();
+            let cos_d = wi.dot(&h);
+
+            if diffuse_weight > 0. {
+                // Burley's diffuse, brighter at grazing angles on rough surfaces
+                let fd90 = 0.5 + 2. * self.roughness * cos_d * cos_d;
+                let retro = (1. + (fd90 - 1.) * schlick_weight(wi.z)) * (1. + (fd90 - 1.) * schlick_weight(wo.z));
+                let diffuse = base.scale(retro / PI);
+                let sheen = lerp(&WHITE, &Principled::tint(&base), self.sheen_tint).scale(self.sheen * schlick_weight(cos_d));
+                value = value + (diffuse + sheen).scale(diffuse_weight * wi.z);
+            }
+            if specular_weight > 0. {
+                let fresnel = schlick(&self.specular_color(&base), cos_d);
+                value = value + fresnel.scale(specular_weight * microfacet_reflection(&self.ggx(), &wo, &wi, &h));
+            }
+            if clearcoat_weight > 0. {
+                let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+                value = value + WHITE.scale(clearcoat_weight * fresnel * microfacet_reflection(&self.clearcoat_ggx(), &wo, &wi, &h));
+            }
+        }
+        if transmission_weight > 0. {
+            let transmitted = self.dielectric().eval(ray, hit_record, direction).scale(transmission_weight);
+            // Tinted by the base color when entering
+            value = value + if wi.z < 0. { transmitted * base } else { transmitted };
+        }
+        value
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vector3<f32>) -> f32 {
+        if !hit_record.front_face {
+            return self.dielectric().pdf(ray, hit_record, direction)
+        }
+        let (wo, wi) = Principled::local(ray, hit_record, direction);
+        let base = self.base_color.value(hit_record.u, hit_record.v, &hit_record.position);
+        let [diffuse, specular, clearcoat, transmission] = self.probabilities(&base, &wo);
+
+        let mut pdf = 0.;
+        if wo.z > 0. && wi.z > 0. {
+            let h = (wo + wi).normalize();
+            pdf += diffuse * wi.z / PI;
+            pdf += specular * microfacet_pdf(&self.ggx(), &wo, &h);
+            pdf += clearcoat * microfacet_pdf(&self.clearcoat_ggx(), &wo, &h);
+        }
+        if transmission > 0. {
+            pdf += transmission * self.dielectric().pdf(ray, hit_record, direction);
+        }
+        pdf
+    }
+}
+
+#[test]
+fn test_principled() {
+    // Whatever the lobe sampled, the density of the direction is the one of
+    // the mixture: it adds up to the chances of sampling a direction
+    let principled = Material::Principled(Principled {
+        metallic: 0.3,
+        roughness: 0.4,
+        sheen: 0.5,
+        clearcoat: 1.,
+        clearcoat_gloss: 0.5,
+        transmission: 0.5,
+        ..Principled::new(Color::new(0.8, 0.4, 0.2))
+    });
+    let ray = Ray::new(Vector3::new(-0.5, 0., 1.), Vector3::new(0.5, 0., -1.));
+    let hit_record = HitRecord {
+        position: Vector3::zeros(),
+        normal: Vector3::new(0., 0., 1.),
+        front_face: true,
+        t: 1.,
+        u: 0.,
+        v: 0.,
+        material: &principled,
+        incoming: ray.direction,
+    };
+
+    // Integrate over cells of equal solid angle, uniform in z and phi
+    let n = 300;
+    let mut integral = 0.;
+    for i in 0..n {
+        let z = -1. + (i as f32 + 0.5) * 2. / n as f32;
+        let r = (1. - z * z).sqrt();
+        for j in 0..n {
+            let phi = (j as f32 + 0.5) * 2. * PI / n as f32;
+            let direction = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+            integral += principled.pdf(&ray, &hit_record, &direction) as f64;
+        }
+    }
+    let integral = integral * 4. * std::f64::consts::PI / (n * n) as f64;
+
+    let samples = 20_000;
+    let mut sampled = 0;
+    let mut mean = BLACK;
+    for _ in 0..samples {
+        if let Some(scatter) = principled.scatter(&ray, &hit_record) {
+            sampled += 1;
+            mean = mean + scatter.attenuation().scale(1. / samples as f32);
+        }
+    }
+    let sampled = sampled as f64 / samples as f64;
+    assert!((integral - sampled).abs() < 0.01, "{} {}", integral, sampled);
+    // Less light leaves than arrives, with some slack for the noise
+    assert!(mean.r < 1.05 && mean.g < 1.05 && mean.b < 1.05, "{:?}", mean);
+}
@@ -0,0 +1,53 @@
+use std::cell::RefCell;
+use rand::{rngs::StdRng, Error, RngCore, SeedableRng};
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Handle on the random generator of the current thread, used instead of
+/// `rand::thread_rng()` so that a render can be reproduced from a seed.
+#[derive(Debug, Clone, Copy)]
+pub struct Rng;
+
+pub fn rng() -> Rng {
+    Rng
+}
+
+/// Restart the generator of the current thread from `seed`.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+#[test]
+fn test_seed() {
+    use rand::Rng as _;
+
+    seed(42);
+    let a: Vec<f32> = (0..8).map(|_| rng().gen()).collect();
+    seed(42);
+    let b: Vec<f32> = (0..8).map(|_| rng().gen()).collect();
+    assert_eq!(a, b);
+
+    seed(43);
+    let c: Vec<f32> = (0..8).map(|_| rng().gen()).collect();
+    assert_ne!(a, c);
+}
@@ -6,6 +6,8 @@ use crate::material::Material;
 pub struct Ray {
     pub origin: Vector3<f32>,
     pub direction: Vector3<f32>,
+    /// Instant within the shutter interval, at which moving objects are seen.
+    pub time: f32,
 }
 
 impl Ray {
@@ -14,7 +16,11 @@ impl Ray {
     }
 
     pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Ray {
-        Ray { origin, direction }
+        Ray { origin, direction, time: 0. }
+    }
+
+    pub fn with_time(origin: Vector3<f32>, direction: Vector3<f32>, time: f32) -> Ray {
+        Ray { origin, direction, time }
     }
 }
 
@@ -23,6 +29,8 @@ pub struct HitRecord<'material>{
     pub position: Vector3<f32>,
     pub normal: Vector3<f32>,
     pub t: f32,
+    pub u: f32,
+    pub v: f32,
     pub material: &'material Material,
     pub front_face: bool,
     pub incoming: Vector3<f32>,
@@ -1,166 +1,276 @@
-use std::{fs, io::Write, sync::{mpsc, Arc}, time::Instant};
+use std::{io, sync::atomic::{AtomicUsize, Ordering}, time::Instant};
 use rand::prelude::*;
-use threadpool::ThreadPool;
+use rayon::prelude::*;
 
 use crate::parameters::*;
 use crate::ray::{Ray, HitRecord};
 use crate::material::Scatterable;
 use crate::primitives::*;
+use crate::bvh::Bvh;
+use crate::lights::LightList;
 use crate::config::Config;
+use crate::background::Background;
 use crate::color::*;
+use crate::image::Image;
+use crate::image_io::{ImageFormat, ImageWriter, write_image};
+use crate::tonemap::tone_map;
+use crate::random;
 
-fn hit_world<'material>(
-    world: &'material Vec<Box<dyn Primitive>>,
-    ray: &Ray, 
-    t_min: f32, 
-    t_max: f32,
-    ) -> Option<HitRecord<'material>> {
-    let mut closest_so_far = t_max;
-    let mut hit_record = None;
-    for object in world {
-        if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
-            closest_so_far = hit.t;
-            hit_record = Some(hit);
-        }
+#[cfg(test)]
+use nalgebra::Vector3;
+#[cfg(test)]
+use crate::{camera::PerspectiveCamera, material::*, tonemap::DisplayTransform};
+
+/// Objects of a scene ready to be rendered: all of them in a BVH, and the
+/// lights also in a list to sample them directly.
+pub struct World<'a> {
+    pub objects: Bvh<'a>,
+    pub lights: LightList<'a>,
+    pub background: &'a Background,
+}
+
+impl<'a> World<'a> {
+    pub fn new(objects: &'a [Box<dyn Primitive>], background: &'a Background) -> World<'a> {
+        let lights = objects.iter()
+            .filter(|object| object.is_light())
+            .map(|object| object.as_ref())
+            .collect();
+        let objects = objects.iter()
+            .map(|object| Box::new(object.as_ref()) as Box<dyn Primitive + 'a>)
+            .collect();
+
+        World { objects: Bvh::new(objects), lights: LightList::new(lights), background }
     }
-    hit_record
 }
 
 pub fn ray_color(
     ray: &Ray,
-    scene: &Config,
+    world: &World,
     depth: usize,
     ) -> Color {
+    trace(ray, world, depth, None)
+}
 
-    if depth <= 0 {
+/// Power heuristic (with an exponent of 2) weighting the sampling strategy
+/// of density `pdf` against the other one.
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0. {
+        return 0.
+    }
+    a / (a + b)
+}
+
+// `bsdf_pdf` is the density with which the previous hit sampled the ray, if
+// it was not specular. The lights were also sampled at that hit, so the
+// emission found by the ray is weighted against the light sampling strategy.
+fn trace(
+    ray: &Ray,
+    world: &World,
+    depth: usize,
+    bsdf_pdf: Option<f32>,
+    ) -> Color {
+
+    if depth == 0 {
         return BLACK;
     }
 
-    let hit = hit_world(&scene.objects, ray, EPSILON, INF);
+    let hit = world.objects.hit(ray, EPSILON, INF);
     match hit {
         Some(hit_record) => {
             let scatter = hit_record.material.scatter(ray, &hit_record);
-            let emitted = hit_record.material.emitted();
+            let mut emitted = hit_record.material.emitted();
+            if let Some(bsdf_pdf) = bsdf_pdf {
+                if !emitted.is_black() {
+                    let light_pdf = world.lights.pdf(&ray.origin, &ray.direction);
+                    emitted = emitted.scale(power_heuristic(bsdf_pdf, light_pdf));
+                }
+            }
             match scatter {
-                Some((scattered_ray, attenuation)) => {
-                    // Scatter and attenuate by the reflectance (= albedo)
-                    emitted + attenuation * ray_color(&scattered_ray, scene, depth - 1)
+                Some(scatter) if scatter.is_specular => {
+                    emitted + scatter.attenuation() * trace(&scatter.ray, world, depth - 1, None)
+                }
+                Some(scatter) => {
+                    emitted
+                        + sample_lights(ray, &hit_record, world)
+                        + scatter.attenuation() * trace(&scatter.ray, world, depth - 1, Some(scatter.pdf))
                 }
                 None => emitted
             }
         }
-        None => {
-            // let unit_direction = ray.direction.normalize();
-            // let t = 0.5 * (unit_direction.y + 1.);
-            // let blue = Color::new(0.5, 0.7, 1.0);
-
-            // WHITE.scale(1. - t) + blue.scale(t)
-            // println!("Sent to the void");
-            // println!("-- Origin: {:?}", ray.origin);
-            // println!("-- Direction: {:?}\n", ray.direction);
-            BLACK
-            // WHITE.scale(0.1)
-        }
+        // The background is not sampled as a light, it is only found by
+        // the rays leaving the scene
+        None => world.background.color(&ray.direction),
     }
 }
 
-fn blue_sky(
-    ray: &Ray,
-    _scene: &Config,
-    _depth: usize,
-    ) -> Color {
-    let unit_direction = ray.direction.normalize();
-    let t = 0.5 * (unit_direction.y + 1.);
-    let blue = Color::new(0.5, 0.7, 1.0);
+/// Direct lighting at a non specular hit: aim a shadow ray at a random light
+/// and weight whatever it sees by the BSDF and the density of the direction,
+/// balanced against the chances of the BSDF sampling the same direction.
+fn sample_lights(ray: &Ray, hit_record: &HitRecord, world: &World) -> Color {
+    if world.lights.is_empty() {
+        return BLACK
+    }
 
-    WHITE.scale(1. - t) + blue.scale(t)
+    let direction = world.lights.sample(&hit_record.position);
+    let light_pdf = world.lights.pdf(&hit_record.position, &direction);
+    if light_pdf <= 0. {
+        return BLACK
+    }
+
+    let bsdf = hit_record.material.eval(ray, hit_record, &direction);
+    if bsdf.is_black() {
+        return BLACK
+    }
+    let bsdf_pdf = hit_record.material.pdf(ray, hit_record, &direction);
+    let weight = power_heuristic(light_pdf, bsdf_pdf);
+
+    let shadow_ray = Ray::with_time(hit_record.position, direction, ray.time);
+    match world.objects.hit(&shadow_ray, EPSILON, INF) {
+        Some(light_hit) => (bsdf * light_hit.material.emitted()).scale(weight / light_pdf),
+        None => BLACK,
+    }
+}
+
+/// Render a scene into a file, encoded as guessed from its extension.
+pub fn render(scene: &Config, filename: &str) -> io::Result<()> {
+    let format = ImageFormat::from_path(filename).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported image format: {}", filename))
+    })?;
+    render_with(scene, filename, format.writer().as_ref())
 }
 
-pub fn render(scene: Config, filename: &str) {
-    let scene = Arc::new(scene);
-    let (tx, rx) = mpsc::channel();
-    let n_workers = 8;
-    let pool = ThreadPool::new(n_workers);
+pub fn render_with(scene: &Config, filename: &str, writer: &dyn ImageWriter) -> io::Result<()> {
+    let image = render_to_buffer(scene);
+    let image = if writer.is_hdr() { image } else { tone_map(&image, &scene.display) };
+    write_image(filename, writer, &image)
+}
 
-    let mut image = vec![vec![(0, 0, 0); scene.width as usize]; scene.height as usize];
+/// Render a scene to linear radiance.
+pub fn render_to_buffer(scene: &Config) -> Image {
+    let world = World::new(&scene.objects, &scene.background);
+    let n_workers = scene.threads.unwrap_or_else(|| {
+        std::thread::available_parallelism().map(|n| n.get()).unwrap_or(8)
+    });
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(n_workers)
+        .build()
+        .expect("cannot start the rendering threads");
 
     let scale = 1. / scene.samples_per_pixel as f32;
 
     let start = Instant::now();
     println!("Starting rendering...");
 
-    for i in 0..scene.height {
-        let tx_row = tx.clone();
-        let scene = Arc::clone(&scene);
-        pool.execute(move || {
-            for j in 0..scene.width {
-                let mut rng = thread_rng();
+    let n_rows_computed = AtomicUsize::new(0);
+    let twenty_percent = (scene.height / 5).max(1);
+    let render_row = |i: usize| {
+        if let Some(seed) = scene.seed {
+            random::seed(row_seed(seed, i));
+        }
+        let mut rng = random::rng();
+        let row: Vec<Color> = (0..scene.width)
+            .map(|j| {
                 let mut color = BLACK;
                 for _ in 0..scene.samples_per_pixel {
                     let u = (j as f32 + rng.gen::<f32>()) / scene.width as f32;
                     let v = (i as f32 + rng.gen::<f32>())/ scene.height as f32;
-                    let ray = scene.camera.get_ray(u, v);
-                    color = color + ray_color(&ray, &scene, scene.depth);
+                    if let Some(ray) = scene.camera.get_ray(u, v) {
+                        color = color + ray_color(&ray, &world, scene.depth);
+                    }
                 }
+                color.scale(scale)
+            })
+            .collect();
 
-                let r = (scale * color.r).sqrt();
-                let g = (scale * color.g).sqrt();
-                let b = (scale * color.b).sqrt();
+        let n = n_rows_computed.fetch_add(1, Ordering::Relaxed) + 1;
+        if n.is_multiple_of(twenty_percent) {
+            println!("Rendered {} / {} rows (~ {}%), time elapsed: {:?}",
+                     n,
+                     scene.height,
+                     (n as f32 / scene.height as f32) * 100.,
+                     start.elapsed())
+        }
+        row
+    };
 
-                // Don't ask why, just admire the result.
-                tx_row.send(((scene.height - 1 - i, j), Color::new(r, g, b))).unwrap()
+    // Rows are rendered from the bottom of the image
+    let rows: Vec<Vec<Color>> = pool.install(|| {
+        (0..scene.height).into_par_iter().rev().map(render_row).collect()
+    });
+    Image::from_pixels(scene.width, scene.height, rows.concat())
+}
 
-            }
-        })
-    }
+// Seed of the generator rendering row `i`, spread so that neighbouring rows
+// get unrelated sequences
+fn row_seed(seed: u64, i: usize) -> u64 {
+    seed ^ (i as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
 
-    drop(tx);
-
-    let mut n_pixels_computed = 0;
-    let total = scene.width * scene.height;
-    let twenty_percent = total / 5;
-    for ((i, j), color) in rx {
-        n_pixels_computed += 1;
-        let r = clamp(color.r * 255., 0., 255.);
-        let g = clamp(color.g * 255., 0., 255.); 
-        let b = clamp(color.b * 255., 0., 255.); 
-        image[i][j] = (r as u8, g as u8, b as u8);
-        if n_pixels_computed % twenty_percent == 0 {
-            println!("Rendered {} / {} pixels (~ {}%), time elapsed: {:?}",
-                     n_pixels_computed,
-                     total,
-                     (n_pixels_computed as f32 / total as f32) * 100.,
-                     start.elapsed())
-        }
+#[test]
+fn test_render_to_buffer() {
+    // Inside a glowing sphere, every sample sees its emission
+    let light = Material::Light(Light::new(Color::new(2., 1., 0.5)));
+    let scene = Config {
+        width: 8,
+        height: 4,
+        samples_per_pixel: 2,
+        depth: 4,
+        background: Background::default(),
+        camera: Box::new(PerspectiveCamera::new(ORIGIN, Vector3::new(0., 0., -1.), Vector3::new(0., 1., 0.), 90., 2., 0., 1.)),
+        objects: vec![Box::new(Sphere::new(ORIGIN, 10., light))],
+        threads: Some(2),
+        seed: Some(1),
+        display: DisplayTransform::default(),
+    };
+    let image = render_to_buffer(&scene);
+    assert_eq!((image.width, image.height, image.pixels.len()), (8, 4, 32));
+    assert!(image.pixels.iter().all(|c| c.r == 2. && c.g == 1. && c.b == 0.5));
+    assert!((tone_map(&image, &scene.display).get(3, 2).r - 1.).abs() < 1e-6);
+}
+
+#[test]
+fn test_power_heuristic() {
+    for (a, b) in [(1., 1.), (0.5, 3.), (2., 0.), (1e-3, 10.)] {
+        assert!((power_heuristic(a, b) + power_heuristic(b, a) - 1.).abs() < 1e-6);
     }
+    assert_eq!(power_heuristic(2., 0.), 1.);
+    assert_eq!(power_heuristic(0., 0.), 0.);
+}
 
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .append(true)
-        .create(true)
-        .open(filename)
-        .unwrap();
-    
-    file.write_all("P3\n".as_bytes()).expect("write failed");
-    file.write_all(format!("{} {}\n", scene.width, scene.height).as_bytes()).expect("write failed");
-    file.write_all("255\n".as_bytes()).expect("write failed");
-
-    for row in image {
-        for (r, g, b) in row {
-            file.write_all(format!("{} {} {}\n",r as u8, g as u8, b as u8)
-                           .as_bytes()).expect("write failed");
+#[test]
+fn test_light_sampling() {
+    // Direct lighting of a diffuse floor under a square light: sampling the
+    // BSDF only, the light only, or both weighted by MIS, agree
+    let objects: Vec<Box<dyn Primitive>> = vec![
+        Box::new(RectangleXZ::new(-10., 10., -10., 10., 0., Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+        Box::new(RectangleXZ::new(-1., 1., -1., 1., 1., Material::Light(Light::new(WHITE)))),
+    ];
+    let background = Background::default();
+    let world = World::new(&objects, &background);
+    let ray = Ray::new(Vector3::new(0., 0.5, 0.), Vector3::new(0., -1., 0.));
+    let hit_record = world.objects.hit(&ray, EPSILON, INF).unwrap();
+
+    let samples = 20_000;
+    let (mut bsdf_only, mut light_only, mut mis) = (0f64, 0f64, 0f64);
+    for _ in 0..samples {
+        let scatter = hit_record.material.scatter(&ray, &hit_record).unwrap();
+        if let Some(light_hit) = world.objects.hit(&scatter.ray, EPSILON, INF) {
+            bsdf_only += (scatter.attenuation() * light_hit.material.emitted()).r as f64;
         }
-    }
 
-}
+        let direction = world.lights.sample(&hit_record.position);
+        let light_pdf = world.lights.pdf(&hit_record.position, &direction);
+        let bsdf = hit_record.material.eval(&ray, &hit_record, &direction);
+        let shadow_ray = Ray::new(hit_record.position, direction);
+        if let Some(light_hit) = world.objects.hit(&shadow_ray, EPSILON, INF) {
+            light_only += (bsdf * light_hit.material.emitted()).r as f64 / light_pdf as f64;
+        }
 
-fn clamp(number: f32, min: f32, max: f32) -> f32 {
-    let mut res = number;
-    if number < min {
-        res = min
-    } else if number > max {
-        res = max
+        // Only the direct lighting, the light does not scatter
+        mis += trace(&ray, &world, 2, None).r as f64;
     }
-    res
+    let (bsdf_only, light_only, mis) = (bsdf_only / samples as f64, light_only / samples as f64, mis / samples as f64);
+    assert!((bsdf_only - mis).abs() < 0.03 * mis, "{} {}", bsdf_only, mis);
+    assert!((light_only - mis).abs() < 0.03 * mis, "{} {}", light_only, mis);
 }
-
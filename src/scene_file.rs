@@ -0,0 +1,695 @@
+use std::{collections::BTreeMap, fmt, fs, path::{Path, PathBuf}, sync::Arc};
+use nalgebra::Vector3;
+use serde::Deserialize;
+
+use crate::{
+    background::*, camera::*, color::*, config::Config, material::*, principled::Principled, primitives::*, texture::*, tonemap::*,
+    obj::{load_obj, ObjError}, image_io::ImageError,
+};
+
+#[cfg(test)]
+use crate::{ray::Ray, parameters::{EPSILON, INF}};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        file: String,
+        message: String,
+    },
+    Invalid {
+        file: String,
+        key: String,
+        message: String,
+    },
+    Obj(ObjError),
+    Texture(ImageError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            SceneError::Parse { file, message } => write!(f, "{}: {}", file, message.trim_end()),
+            SceneError::Invalid { file, key, message } => write!(f, "{}: {}: {}", file, key, message),
+            SceneError::Obj(error) => write!(f, "{}", error),
+            SceneError::Texture(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+// Layout of a scene file. Colors and points are arrays of three numbers,
+// materials and textures are tables referred to by their name, built in
+// the order of their names so that errors do not change from run to run.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    width: usize,
+    height: usize,
+    samples_per_pixel: usize,
+    depth: usize,
+    camera: CameraFile,
+    #[serde(default)]
+    display: DisplayFile,
+    // Black if not given
+    background: Option<BackgroundFile>,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureFile>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialFile>,
+    #[serde(default)]
+    objects: Vec<ObjectFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraFile {
+    #[serde(default)]
+    projection: ProjectionFile,
+    // View height of the orthographic projection, in world units
+    height: Option<f32>,
+    look_from: [f32; 3],
+    look_at: [f32; 3],
+    #[serde(default = "default_vup")]
+    vup: [f32; 3],
+    // Vertical field of view of the perspective camera, or angle across the
+    // fisheye circle, in degrees
+    vfov: Option<f32>,
+    // Defaults to width / height
+    aspect_ratio: Option<f32>,
+    #[serde(default)]
+    aperture: f32,
+    // Defaults to the distance to `look_at`
+    focus_distance: Option<f32>,
+    // Number of blades of a polygonal aperture, round if not given
+    blades: Option<u32>,
+    #[serde(default)]
+    blade_rotation: f32,
+    // Opening of the shutter, motion is blurred over it
+    #[serde(default)]
+    shutter_open: f32,
+    #[serde(default)]
+    shutter_close: f32,
+}
+
+fn default_vup() -> [f32; 3] {
+    [0., 1., 0.]
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ProjectionFile {
+    #[default]
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Equirectangular,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct DisplayFile {
+    #[serde(default)]
+    exposure: f32,
+    #[serde(default)]
+    tone_mapper: ToneMapperFile,
+    // White point of Reinhard, which never reaches 1 without it
+    white: Option<f32>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ToneMapperFile {
+    #[default]
+    Clamp,
+    Reinhard,
+    Hable,
+    Aces,
+}
+
+// The gradient defaults to the sky of the book. Environment maps are
+// rotated around Y by `rotation` degrees.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundFile {
+    Color { color: [f32; 3] },
+    Gradient { bottom: Option<[f32; 3]>, top: Option<[f32; 3]> },
+    Environment {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+}
+
+fn default_intensity() -> f32 {
+    1.
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum WrapFile {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum FilterFile {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureFile {
+    Solid { color: [f32; 3] },
+    Checker { even: [f32; 3], odd: [f32; 3], scale: f32 },
+    Image { path: PathBuf, wrap: Option<WrapFile>, filter: Option<FilterFile> },
+    Noise { seed: u64, scale: f32, #[serde(default = "default_octaves")] octaves: usize, color: [f32; 3] },
+    Marble { seed: u64, scale: f32, base: [f32; 3], vein: [f32; 3] },
+    Wood { seed: u64, scale: f32, rings: f32, light: [f32; 3], dark: [f32; 3] },
+    Cellular { seed: u64, scale: f32, color: [f32; 3] },
+}
+
+fn default_octaves() -> usize {
+    1
+}
+
+// Lambertian and Metal take either a constant `albedo` or the name of a
+// texture, in `texture`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialFile {
+    Lambertian {
+        albedo: Option<[f32; 3]>,
+        texture: Option<String>,
+    },
+    Metal {
+        albedo: Option<[f32; 3]>,
+        texture: Option<String>,
+        #[serde(default)]
+        fuzz: f32,
+        fuzz_texture: Option<String>,
+    },
+    // Either a measured `metal`, or its complex index of refraction
+    Conductor {
+        metal: Option<MetalFile>,
+        eta: Option<[f32; 3]>,
+        k: Option<[f32; 3]>,
+        #[serde(default)]
+        roughness: f32,
+    },
+    // Frosted if `roughness` is given. Tinted by either an `absorption`
+    // coefficient, or the `transmittance` left after `distance`
+    Dielectric {
+        index_of_refraction: f32,
+        #[serde(default)]
+        roughness: f32,
+        absorption: Option<[f32; 3]>,
+        transmittance: Option<[f32; 3]>,
+        #[serde(default = "default_distance")]
+        distance: f32,
+    },
+    // Diffuse `albedo` or `texture` under a smooth or rough coat
+    Plastic {
+        albedo: Option<[f32; 3]>,
+        texture: Option<String>,
+        #[serde(default = "default_plastic_index")]
+        index_of_refraction: f32,
+        #[serde(default)]
+        roughness: f32,
+    },
+    // Base color like Lambertian, the knobs default to a rough plastic
+    Principled {
+        albedo: Option<[f32; 3]>,
+        texture: Option<String>,
+        metallic: Option<f32>,
+        roughness: Option<f32>,
+        specular: Option<f32>,
+        specular_tint: Option<f32>,
+        sheen: Option<f32>,
+        sheen_tint: Option<f32>,
+        clearcoat: Option<f32>,
+        clearcoat_gloss: Option<f32>,
+        transmission: Option<f32>,
+    },
+    Light {
+        color: [f32; 3],
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum MetalFile {
+    Gold,
+    Copper,
+    Aluminum,
+    Silver,
+}
+
+/// Shape with its material, rotated around Y (in degrees) then translated.
+/// With `rotate_y_end` or `translate_end`, the object moves from the first
+/// pose at time 0 to the second one at time 1.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectFile {
+    shape: ShapeFile,
+    // Meshes can take their materials from their MTL files
+    material: Option<String>,
+    rotate_y: Option<f32>,
+    translate: Option<[f32; 3]>,
+    rotate_y_end: Option<f32>,
+    translate_end: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeFile {
+    Sphere { center: [f32; 3], radius: f32 },
+    MovingSphere {
+        center0: [f32; 3],
+        center1: [f32; 3],
+        radius: f32,
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+    },
+    RectXy { x0: f32, x1: f32, y0: f32, y1: f32, k: f32 },
+    RectXz { x0: f32, x1: f32, z0: f32, z1: f32, k: f32 },
+    RectYz { y0: f32, y1: f32, z0: f32, z1: f32, k: f32 },
+    Cuboid { min: [f32; 3], max: [f32; 3] },
+    Triangle { vertices: [[f32; 3]; 3] },
+    Mesh { path: PathBuf },
+}
+
+fn default_distance() -> f32 {
+    1.
+}
+
+fn default_plastic_index() -> f32 {
+    1.5
+}
+
+fn default_time1() -> f32 {
+    1.
+}
+
+fn vector(v: [f32; 3]) -> Vector3<f32> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn color(c: [f32; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
+
+struct Builder<'a> {
+    file: &'a str,
+    directory: &'a Path,
+    textures: BTreeMap<String, Arc<dyn Texture>>,
+    materials: BTreeMap<String, Material>,
+}
+
+impl<'a> Builder<'a> {
+    fn error<T>(&self, key: String, message: String) -> Result<T, SceneError> {
+        Err(SceneError::Invalid { file: self.file.to_string(), key, message })
+    }
+
+    fn texture(&self, key: String, name: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        match self.textures.get(name) {
+            Some(texture) => Ok(Arc::clone(texture)),
+            None => self.error(key, format!("unknown texture `{}`", name)),
+        }
+    }
+
+    // Exactly one of a constant color and a texture name
+    fn albedo(&self, key: &str, albedo: Option<[f32; 3]>, texture: &Option<String>) -> Result<Arc<dyn Texture>, SceneError> {
+        match (albedo, texture) {
+            (Some(c), None) => Ok(Arc::new(SolidColor::new(color(c)))),
+            (None, Some(name)) => self.texture(format!("{}.texture", key), name),
+            _ => self.error(key.to_string(), "expected one of `albedo` and `texture`".to_string()),
+        }
+    }
+
+    fn build_texture(&self, texture: &TextureFile) -> Result<Arc<dyn Texture>, SceneError> {
+        let texture: Arc<dyn Texture> = match texture {
+            TextureFile::Solid { color: c } => Arc::new(SolidColor::new(color(*c))),
+            TextureFile::Checker { even, odd, scale } => Arc::new(Checker::from_colors(color(*even), color(*odd), *scale)),
+            TextureFile::Image { path, wrap, filter } => {
+                let mut image = ImageTexture::load(self.directory.join(path)).map_err(SceneError::Texture)?;
+                if let Some(wrap) = wrap {
+                    image = image.with_wrap(match wrap {
+                        WrapFile::Repeat => WrapMode::Repeat,
+                        WrapFile::Clamp => WrapMode::Clamp,
+                        WrapFile::Mirror => WrapMode::Mirror,
+                    });
+                }
+                if let Some(filter) = filter {
+                    image = image.with_filter(match filter {
+                        FilterFile::Nearest => Filter::Nearest,
+                        FilterFile::Bilinear => Filter::Bilinear,
+                    });
+                }
+                Arc::new(image)
+            }
+            TextureFile::Noise { seed, scale, octaves, color: c } => Arc::new(NoiseTexture::new(*seed, *scale, *octaves, color(*c))),
+            TextureFile::Marble { seed, scale, base, vein } => Arc::new(Marble::new(*seed, *scale, color(*base), color(*vein))),
+            TextureFile::Wood { seed, scale, rings, light, dark } => Arc::new(Wood::new(*seed, *scale, *rings, color(*light), color(*dark))),
+            TextureFile::Cellular { seed, scale, color: c } => Arc::new(CellularTexture::new(*seed, *scale, color(*c))),
+        };
+        Ok(texture)
+    }
+
+    fn build_material(&self, key: &str, material: &MaterialFile) -> Result<Material, SceneError> {
+        let material = match material {
+            MaterialFile::Lambertian { albedo, texture } => {
+                Material::Lambertian(Lambertian::textured(self.albedo(key, *albedo, texture)?))
+            }
+            MaterialFile::Metal { albedo, texture, fuzz, fuzz_texture } => {
+                let albedo = self.albedo(key, *albedo, texture)?;
+                let fuzz = match fuzz_texture {
+                    Some(name) => self.texture(format!("{}.fuzz_texture", key), name)?,
+                    None => Arc::new(SolidColor::new(Color::new(*fuzz, *fuzz, *fuzz))),
+                };
+                Material::Metal(Metal::textured(albedo, fuzz))
+            }
+            MaterialFile::Conductor { metal, eta, k, roughness } => {
+                let conductor = match (metal, eta, k) {
+                    (Some(MetalFile::Gold), None, None) => Conductor::gold(*roughness),
+                    (Some(MetalFile::Copper), None, None) => Conductor::copper(*roughness),
+                    (Some(MetalFile::Aluminum), None, None) => Conductor::aluminum(*roughness),
+                    (Some(MetalFile::Silver), None, None) => Conductor::silver(*roughness),
+                    (None, Some(eta), Some(k)) => Conductor::new(color(*eta), color(*k), *roughness),
+                    _ => return self.error(key.to_string(), "expected either `metal`, or both `eta` and `k`".to_string()),
+                };
+                Material::Conductor(conductor)
+            }
+            MaterialFile::Dielectric { index_of_refraction, roughness, absorption, transmittance, distance } => {
+                let absorption = match (absorption, transmittance) {
+                    (None, None) => BLACK,
+                    (Some(absorption), None) => color(*absorption),
+                    (None, Some(_)) if *distance <= 0. => {
+                        return self.error(format!("{}.distance", key), "the distance must be positive".to_string())
+                    }
+                    (None, Some(transmittance)) => absorption_for_transmittance(color(*transmittance), *distance),
+                    (Some(_), Some(_)) => {
+                        return self.error(key.to_string(), "expected either `absorption` or `transmittance`".to_string())
+                    }
+                };
+                if *roughness > 0. {
+                    Material::RoughDielectric(RoughDielectric::new(*index_of_refraction, *roughness).with_absorption(absorption))
+                } else {
+                    Material::Dielectric(Dielectric::new(*index_of_refraction).with_absorption(absorption))
+                }
+            }
+            MaterialFile::Plastic { albedo, texture, index_of_refraction, roughness } => {
+                Material::Plastic(Plastic::textured(self.albedo(key, *albedo, texture)?, *index_of_refraction, *roughness))
+            }
+            MaterialFile::Principled {
+                albedo, texture, metallic, roughness, specular, specular_tint, sheen, sheen_tint, clearcoat, clearcoat_gloss,
+                transmission,
+            } => {
+                let default = Principled::textured(self.albedo(key, *albedo, texture)?);
+                Material::Principled(Principled {
+                    metallic: metallic.unwrap_or(default.metallic),
+                    roughness: roughness.unwrap_or(default.roughness),
+                    specular: specular.unwrap_or(default.specular),
+                    specular_tint: specular_tint.unwrap_or(default.specular_tint),
+                    sheen: sheen.unwrap_or(default.sheen),
+                    sheen_tint: sheen_tint.unwrap_or(default.sheen_tint),
+                    clearcoat: clearcoat.unwrap_or(default.clearcoat),
+                    clearcoat_gloss: clearcoat_gloss.unwrap_or(default.clearcoat_gloss),
+                    transmission: transmission.unwrap_or(default.transmission),
+                    ..default
+                })
+            }
+            MaterialFile::Light { color: c } => Material::Light(Light::new(color(*c))),
+        };
+        Ok(material)
+    }
+
+    fn material(&self, key: &str, name: &Option<String>) -> Result<Option<Material>, SceneError> {
+        match name {
+            None => Ok(None),
+            Some(name) => match self.materials.get(name) {
+                Some(material) => Ok(Some(material.clone())),
+                None => self.error(format!("{}.material", key), format!("unknown material `{}`", name)),
+            },
+        }
+    }
+
+    fn build_object(&self, key: &str, object: &ObjectFile) -> Result<Vec<Box<dyn Primitive>>, SceneError> {
+        let material = self.material(key, &object.material)?;
+        let required = || match &material {
+            Some(material) => Ok(material.clone()),
+            None => self.error(format!("{}.material", key), "missing material".to_string()),
+        };
+
+        let shapes: Vec<Box<dyn Primitive>> = match &object.shape {
+            ShapeFile::Sphere { center, radius } => vec![Box::new(Sphere::new(vector(*center), *radius, required()?))],
+            ShapeFile::MovingSphere { center0, center1, radius, time0, time1 } => {
+                vec![Box::new(MovingSphere::new(vector(*center0), vector(*center1), *time0, *time1, *radius, required()?))]
+            }
+            ShapeFile::RectXy { x0, x1, y0, y1, k } => vec![Box::new(RectangleXY::new(*x0, *x1, *y0, *y1, *k, required()?))],
+            ShapeFile::RectXz { x0, x1, z0, z1, k } => vec![Box::new(RectangleXZ::new(*x0, *x1, *z0, *z1, *k, required()?))],
+            ShapeFile::RectYz { y0, y1, z0, z1, k } => vec![Box::new(RectangleYZ::new(*y0, *y1, *z0, *z1, *k, required()?))],
+            ShapeFile::Cuboid { min, max } => vec![Box::new(RectangularCuboid::new(vector(*min), vector(*max), required()?))],
+            ShapeFile::Triangle { vertices: [p0, p1, p2] } => {
+                vec![Box::new(Triangle::new(vector(*p0), vector(*p1), vector(*p2), required()?))]
+            }
+            ShapeFile::Mesh { path } => {
+                // Faces without a MTL material use the one of the object, or a grey
+                let default = material.clone().unwrap_or(Material::Lambertian(Lambertian::new(Color::new(0.73, 0.73, 0.73))));
+                load_obj(self.directory.join(path), default)
+                    .map_err(SceneError::Obj)?
+                    .into_iter()
+                    .map(|object| Box::new(object.mesh) as Box<dyn Primitive>)
+                    .collect()
+            }
+        };
+
+        if object.rotate_y_end.is_some() || object.translate_end.is_some() {
+            let angle = object.rotate_y.unwrap_or(0.);
+            let offset = object.translate.unwrap_or([0.; 3]);
+            let key0 = TransformKey::new(angle.to_radians(), vector(offset));
+            let key1 = TransformKey::new(
+                object.rotate_y_end.unwrap_or(angle).to_radians(),
+                vector(object.translate_end.unwrap_or(offset)));
+            return Ok(shapes.into_iter()
+                .map(|shape| Box::new(AnimatedTransform::new(shape, key0, key1, 0., 1.)) as Box<dyn Primitive>)
+                .collect())
+        }
+
+        Ok(shapes.into_iter()
+            .map(|mut shape| {
+                if let Some(angle) = object.rotate_y {
+                    shape = Box::new(RotateY::new(angle.to_radians(), shape));
+                }
+                if let Some(offset) = object.translate {
+                    shape = Box::new(Translate::new(shape, vector(offset)));
+                }
+                shape
+            })
+            .collect())
+    }
+}
+
+/// Parse the content of a scene file. `directory` is used to resolve the
+/// paths of meshes and image textures.
+pub fn parse_scene(source: &str, file: &str, directory: &Path) -> Result<Config, SceneError> {
+    let scene: SceneFile = toml::from_str(source)
+        .map_err(|e| SceneError::Parse { file: file.to_string(), message: e.to_string() })?;
+
+    let mut builder = Builder { file, directory, textures: BTreeMap::new(), materials: BTreeMap::new() };
+    if scene.width == 0 || scene.height == 0 {
+        return builder.error("width".to_string(), "the image must not be empty".to_string())
+    }
+    if scene.samples_per_pixel == 0 {
+        return builder.error("samples_per_pixel".to_string(), "at least one sample per pixel is needed".to_string())
+    }
+    if scene.depth == 0 {
+        return builder.error("depth".to_string(), "the ray depth must be at least 1".to_string())
+    }
+
+    for (name, texture) in &scene.textures {
+        let texture = builder.build_texture(texture)?;
+        builder.textures.insert(name.clone(), texture);
+    }
+    for (name, material) in &scene.materials {
+        let material = builder.build_material(&format!("materials.{}", name), material)?;
+        builder.materials.insert(name.clone(), material);
+    }
+
+    let mut objects = Vec::new();
+    for (i, object) in scene.objects.iter().enumerate() {
+        objects.extend(builder.build_object(&format!("objects[{}]", i), object)?);
+    }
+
+    if scene.display.white.is_some_and(|white| white <= 0.) {
+        return builder.error("display.white".to_string(), "the white point must be positive".to_string())
+    }
+    let tone_mapper = match (scene.display.tone_mapper, scene.display.white) {
+        (ToneMapperFile::Reinhard, white) => ToneMapper::Reinhard { white: white.unwrap_or(f32::INFINITY) },
+        (_, Some(_)) => return builder.error("display.white".to_string(), "only used by the `reinhard` tone mapper".to_string()),
+        (ToneMapperFile::Clamp, None) => ToneMapper::Clamp,
+        (ToneMapperFile::Hable, None) => ToneMapper::Hable,
+        (ToneMapperFile::Aces, None) => ToneMapper::Aces,
+    };
+    let display = DisplayTransform { exposure: scene.display.exposure, tone_mapper };
+
+    let background = match &scene.background {
+        None => Background::default(),
+        Some(BackgroundFile::Color { color: c }) => Background::Color(color(*c)),
+        Some(BackgroundFile::Gradient { bottom, top }) => Background::Gradient {
+            bottom: bottom.map(color).unwrap_or(WHITE),
+            top: top.map(color).unwrap_or(SKY_BLUE),
+        },
+        Some(BackgroundFile::Environment { path, rotation, intensity }) => {
+            let map = EnvironmentMap::load(directory.join(path)).map_err(SceneError::Texture)?;
+            Background::Environment(map.with_rotation(rotation.to_radians()).with_intensity(*intensity))
+        }
+    };
+
+    let camera = &scene.camera;
+    let aperture_shape = match camera.blades {
+        None => ApertureShape::Disk,
+        Some(blades) if blades >= 3 => ApertureShape::Polygon { blades, rotation: camera.blade_rotation },
+        Some(_) => return builder.error("camera.blades".to_string(), "an aperture needs at least 3 blades".to_string()),
+    };
+    let projection = match (camera.projection, camera.height) {
+        (ProjectionFile::Orthographic, Some(height)) if height > 0. => Projection::Orthographic { height },
+        (ProjectionFile::Orthographic, _) => return builder.error("camera.height".to_string(), "an orthographic camera needs a positive height".to_string()),
+        (ProjectionFile::Perspective, _) => Projection::Perspective,
+        (ProjectionFile::Fisheye, _) => Projection::Fisheye,
+        (ProjectionFile::Equirectangular, _) => Projection::Equirectangular,
+    };
+    let vfov = match (projection, camera.vfov) {
+        (_, Some(vfov)) => vfov,
+        (Projection::Perspective | Projection::Fisheye, None) => {
+            return builder.error("camera.vfov".to_string(), "missing field of view".to_string())
+        }
+        (_, None) => 0.,
+    };
+    let params = CameraParams {
+        projection,
+        look_from: vector(camera.look_from),
+        look_at: vector(camera.look_at),
+        vup: vector(camera.vup),
+        vfov,
+        aspect_ratio: camera.aspect_ratio.unwrap_or(scene.width as f32 / scene.height as f32),
+        aperture: camera.aperture,
+        focus_distance: camera.focus_distance.unwrap_or((vector(camera.look_from) - vector(camera.look_at)).norm()),
+        aperture_shape,
+        shutter_open: camera.shutter_open,
+        shutter_close: camera.shutter_close,
+    };
+
+    Ok(Config {
+        width: scene.width,
+        height: scene.height,
+        samples_per_pixel: scene.samples_per_pixel,
+        camera: camera_from_params(&params),
+        objects,
+        depth: scene.depth,
+        threads: None,
+        seed: None,
+        display,
+        background,
+    })
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Config, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    parse_scene(&source, &path.display().to_string(), directory)
+}
+
+#[test]
+fn test_parse_scene() {
+    let source = r#"
+width = 200
+height = 100
+samples_per_pixel = 10
+depth = 5
+
+[camera]
+look_from = [0, 0, 0]
+look_at = [0, 0, -1]
+vfov = 90
+
+[background]
+type = "gradient"
+top = [0, 0, 1]
+
+[textures.checker]
+type = "checker"
+even = [1, 1, 1]
+odd = [0, 0, 0]
+scale = 0.5
+
+[materials.ground]
+type = "lambertian"
+texture = "checker"
+
+[materials.glass]
+type = "dielectric"
+index_of_refraction = 1.5
+
+[[objects]]
+shape = { type = "sphere", center = [0, 0, -2], radius = 0.5 }
+material = "glass"
+
+[[objects]]
+shape = { type = "cuboid", min = [0, 0, 0], max = [1, 1, 1] }
+material = "ground"
+rotate_y = 45
+translate = [0, -2, -3]
+"#;
+    let scene = parse_scene(source, "scene.toml", Path::new("")).unwrap();
+    assert_eq!((scene.width, scene.height, scene.samples_per_pixel, scene.depth), (200, 100, 10, 5));
+    assert_eq!(scene.objects.len(), 2);
+    assert!(matches!(scene.background, Background::Gradient { bottom: WHITE, top: Color { b: 1., .. } }));
+
+    let ray = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 0., -1.));
+    let hit = scene.objects[0].hit(&ray, EPSILON, INF).unwrap();
+    assert!(matches!(hit.material, Material::Dielectric(_)));
+
+    // The cuboid is rotated, its corner is on the Z axis
+    let ray = Ray::new(Vector3::new(0., -1.5, 0.), Vector3::new(0., 0., -1.));
+    let hit = scene.objects[1].hit(&ray, EPSILON, INF).unwrap();
+    assert!((hit.position.z + 3.).abs() < 1e-3);
+}
+
+#[test]
+fn test_parse_scene_errors() {
+    let header = "width = 10\nheight = 10\nsamples_per_pixel = 1\ndepth = 1\n[camera]\nlook_from = [0, 0, 0]\nlook_at = [0, 0, -1]\nvfov = 90\n";
+
+    let source = format!("{}[[objects]]\nshape = {{ type = \"sphere\", center = [0, 0, -1], radius = 1 }}\nmaterial = \"gold\"\n", header);
+    let error = parse_scene(&source, "scene.toml", Path::new("")).err().unwrap();
+    assert_eq!(error.to_string(), "scene.toml: objects[0].material: unknown material `gold`");
+
+    let source = format!("{}[materials.red]\ntype = \"lambertian\"\ncolour = [1, 0, 0]\n", header);
+    let error = parse_scene(&source, "scene.toml", Path::new("")).err().unwrap();
+    assert!(error.to_string().contains("unknown field `colour`"), "{}", error);
+
+    let source = header.replace("depth = 1", "depth = 0");
+    let error = parse_scene(&source, "scene.toml", Path::new("")).err().unwrap();
+    assert_eq!(error.to_string(), "scene.toml: depth: the ray depth must be at least 1");
+
+    // The first error in the order of the names is reported
+    let source = format!("{}[materials.b]\ntype = \"lambertian\"\ntexture = \"wood\"\n[materials.a]\ntype = \"lambertian\"\ntexture = \"marble\"\n", header);
+    for _ in 0..10 {
+        let error = parse_scene(&source, "scene.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "scene.toml: materials.a.texture: unknown texture `marble`");
+    }
+
+    let source = format!("{}[display]\ntone_mapper = \"reinhard\"\nwhite = 0\n", header);
+    let error = parse_scene(&source, "scene.toml", Path::new("")).err().unwrap();
+    assert_eq!(error.to_string(), "scene.toml: display.white: the white point must be positive");
+}
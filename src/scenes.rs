@@ -1,10 +1,15 @@
+use std::sync::Arc;
 use nalgebra::Vector3;
 use rand::prelude::*;
 use crate::material::*;
+use crate::texture::*;
 use crate::config::Config;
 use crate::color::Color;
-use crate::camera::Camera;
+use crate::camera::PerspectiveCamera;
+use crate::background::Background;
+use crate::tonemap::DisplayTransform;
 use crate::primitives::*;
+use crate::random;
 
 
 pub fn final_scene() -> Config {
@@ -26,7 +31,7 @@ pub fn final_scene() -> Config {
     let material3 = Material::Metal(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
     objects.push(Box::new(Sphere { center: Vector3::new(4., 1., 0.), radius: 1.0, material: material3 }));
 
-    let mut rng = thread_rng();
+    let mut rng = random::rng();
 
     for a in -11..11 {
         for b in -11..11 {
@@ -52,21 +57,25 @@ pub fn final_scene() -> Config {
         }
     }
 
-    let final_scene = Config {
+    Config {
         height: 200,
         width: 300,
         samples_per_pixel: 50,
         depth: 50,
-        camera: Camera::new(
+        background: Background::sky(),
+        threads: None,
+        seed: None,
+        display: DisplayTransform::default(),
+        camera: Box::new(PerspectiveCamera::new(
             Vector3::new(13., 2., 3.), 
             Vector3::new(0., 0., 0.), 
             Vector3::new(0., 1., 0.),
             20., 
-            3./2.),
+            3./2.,
+            0.1,
+            10.)),
         objects,
-    };
-
-    final_scene
+    }
 }
 
 pub fn three_balls() -> Config {
@@ -77,18 +86,23 @@ pub fn three_balls() -> Config {
     let glass = Material::Dielectric(Dielectric::new(1.5));
     let glass_inside = Material::Dielectric(Dielectric::new(1.5));
 
-    let scene = Config {
+    Config {
         height: 360,
         width: 640,
         samples_per_pixel: 100,
         depth: 50,
-        camera: Camera::new(
+        background: Background::sky(),
+        threads: None,
+        seed: None,
+        display: DisplayTransform::default(),
+        camera: Box::new(PerspectiveCamera::new(
             Vector3::new(-2., 2., 1.), 
             Vector3::new(0., 0., -1.),
             Vector3::new(0., 1., 0.),
             20.,
-            16./9.
-            ),
+            16./9.,
+            0.,
+            1.)),
         objects: vec![
             Box::new(Sphere{
                 center: Vector3::new(0., -100.5, -1.),
@@ -121,9 +135,7 @@ pub fn three_balls() -> Config {
                 material: golden,
             }),
         ]
-    };
-
-    scene
+    }
 }
 
 pub fn simple_light() -> Config {
@@ -131,17 +143,23 @@ pub fn simple_light() -> Config {
     let sphere = Material::Lambertian(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
     let light = Material::Light(Light::new(Color::new(4., 4., 4.)));
 
-    let scene = Config {
+    Config {
         height: 360,
         width: 640,
         samples_per_pixel: 100,
         depth: 50,
-        camera: Camera::new(
+        background: Background::default(),
+        threads: None,
+        seed: None,
+        display: DisplayTransform::default(),
+        camera: Box::new(PerspectiveCamera::new(
             Vector3::new(26., 3., 6.), 
             Vector3::new(0., 2., 0.),
             Vector3::new(0., 1., 0.),
             20.,
-            16./9.),
+            16./9.,
+            0.,
+            1.)),
         objects: vec![
             Box::new(Sphere{
                 center: Vector3::new(0., -1000., 0.),
@@ -156,9 +174,46 @@ pub fn simple_light() -> Config {
             Box::new(RectangleXY::new(3., 5., 1., 3., -2., light)),
         ]
 
-    };
+    }
+}
+
+pub fn noise_textures() -> Config {
+    let marble = Material::Lambertian(Lambertian::textured(Arc::new(
+        Marble::new(1, 4., Color::new(0.9, 0.9, 0.85), Color::new(0.2, 0.2, 0.25)))));
+    let wood = Material::Lambertian(Lambertian::textured(Arc::new(
+        Wood::new(2, 1., 8., Color::new(0.75, 0.5, 0.3), Color::new(0.4, 0.2, 0.1)))));
+    let cells = Material::Lambertian(Lambertian::textured(Arc::new(
+        CellularTexture::new(3, 4., Color::new(0.3, 0.5, 0.8)))));
+    let brushed = Material::Metal(Metal::textured(
+        Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+        Arc::new(NoiseTexture::new(4, 8., 7, Color::new(0.5, 0.5, 0.5)))));
+    let light = Material::Light(Light::new(Color::new(4., 4., 4.)));
 
-    scene
+    Config {
+        height: 360,
+        width: 640,
+        samples_per_pixel: 100,
+        depth: 50,
+        background: Background::default(),
+        threads: None,
+        seed: None,
+        display: DisplayTransform::default(),
+        camera: Box::new(PerspectiveCamera::new(
+            Vector3::new(13., 3., 6.), 
+            Vector3::new(0., 1., 0.),
+            Vector3::new(0., 1., 0.),
+            30.,
+            16./9.,
+            0.,
+            1.)),
+        objects: vec![
+            Box::new(Sphere::new(Vector3::new(0., -1000., 0.), 1000., marble)),
+            Box::new(Sphere::new(Vector3::new(0., 1., -2.5), 1., wood)),
+            Box::new(Sphere::new(Vector3::new(0., 1., 0.), 1., cells)),
+            Box::new(Sphere::new(Vector3::new(0., 1., 2.5), 1., brushed)),
+            Box::new(RectangleXZ::new(-3., 3., -5., 5., 8., light)),
+        ]
+    }
 }
 
 pub fn cornell_box() -> Config {
@@ -170,17 +225,23 @@ pub fn cornell_box() -> Config {
     let ceiling = Material::Lambertian(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
     let light = Material::Light(Light::new(Color::new(15., 15., 15.)));
 
-    let scene = Config {
+    Config {
         height: 400,
         width: 400,
         samples_per_pixel: 100,
         depth: 50,
-        camera: Camera::new(
+        background: Background::default(),
+        threads: None,
+        seed: None,
+        display: DisplayTransform::default(),
+        camera: Box::new(PerspectiveCamera::new(
             Vector3::new(278., 278., -800.), 
             Vector3::new(278., 278., 0.),
             Vector3::new(0., 1., 0.),
             40.,
-            1.),
+            1.,
+            0.,
+            1.)),
         objects: vec![
             Box::new(RectangleYZ::new(0., 555., -1000., 555., 555., green)),
             Box::new(RectangleYZ::new(0., 555., -1000., 555., 0., red)),
@@ -190,9 +251,7 @@ pub fn cornell_box() -> Config {
             Box::new(RectangleXY::new(0., 555., 0., 555., 555., back)),
             Box::new(RectangleXY::new(0., 555., 0., 555., -1000., front)),
         ]
-    };
-
-    scene
+    }
 }
 
 pub fn small_cornell_box() -> Config {
@@ -205,19 +264,25 @@ pub fn small_cornell_box() -> Config {
     let box1 = Material::Lambertian(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
     let box2 = Material::Lambertian(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
     let light_front = Material::Light(Light::new(Color::new(15., 15., 15.)));
-    let light_back = Material::Light(Light::new(Color::new(15., 15., 15.)));
+    let _light_back = Material::Light(Light::new(Color::new(15., 15., 15.)));
 
-    let scene = Config {
+    Config {
         height: 400,
         width: 400,
         samples_per_pixel: 10,
         depth: 50,
-        camera: Camera::new(
+        background: Background::default(),
+        threads: None,
+        seed: None,
+        display: DisplayTransform::default(),
+        camera: Box::new(PerspectiveCamera::new(
             Vector3::new(0., 10., -20.), 
             Vector3::new(0., 10., 20.),
             Vector3::new(0., 1., 0.),
             45.,
-            1.),
+            1.,
+            0.,
+            1.)),
         objects: vec![
             Box::new(RectangleYZ::new(0., 20., -20., 20., 10., green)),
             Box::new(RectangleYZ::new(0., 20., -20., 20., -10., red)),
@@ -237,7 +302,23 @@ pub fn small_cornell_box() -> Config {
                     box2)),
             
         ]
-    };
+    }
+}
+
+pub type SceneBuilder = fn() -> Config;
+
+/// Built-in scenes, with the name of the function building them.
+pub const SCENES: [(&str, SceneBuilder); 6] = [
+    ("final_scene", final_scene),
+    ("three_balls", three_balls),
+    ("simple_light", simple_light),
+    ("noise_textures", noise_textures),
+    ("cornell_box", cornell_box),
+    ("small_cornell_box", small_cornell_box),
+];
 
-    scene
+pub fn scene_by_name(name: &str) -> Option<Config> {
+    SCENES.iter()
+        .find(|(scene_name, _)| *scene_name == name)
+        .map(|(_, build)| build())
 }
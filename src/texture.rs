@@ -0,0 +1,296 @@
+use std::{fmt::Debug, path::Path, sync::Arc};
+use nalgebra::Vector3;
+
+use crate::{color::*, noise::*, image_io::*};
+
+#[cfg(test)]
+use crate::parameters::ORIGIN;
+
+/// Spatially varying color, looked up with the surface coordinates of a hit
+/// and its position in world space.
+pub trait Texture: Send + Sync + Debug {
+    fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Color;
+}
+
+#[derive(Debug, Clone)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: &Vector3<f32>) -> Color {
+        self.color
+    }
+}
+
+/// 3D checkerboard alternating between two textures, with cells of size `scale`.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f32,
+}
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f32) -> Checker {
+        Checker { even, odd, scale }
+    }
+
+    pub fn from_colors(even: Color, odd: Color, scale: f32) -> Checker {
+        Checker::new(Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)), scale)
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Color {
+        let cell = (p / self.scale).map(|x| x.floor() as i64);
+        if (cell.x + cell.y + cell.z).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// How texture coordinates outside of [0, 1] are brought back into the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+/// Texture backed by an array of linear pixels, stored row by row starting
+/// from the top of the image. `v` goes up, so `v = 0` is the bottom row.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    pub wrap: WrapMode,
+    pub filter: Filter,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> ImageTexture {
+        assert_eq!(pixels.len(), width * height, "image size does not match its dimensions");
+        ImageTexture { width, height, pixels, wrap: WrapMode::Clamp, filter: Filter::Nearest }
+    }
+
+    /// Load an image file. Low dynamic range files are assumed to be sRGB
+    /// encoded. The texture is filtered bilinearly and repeated by default.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageTexture, ImageError> {
+        let image = read_image(path)?;
+        let pixels = if image.linear {
+            image.pixels
+        } else {
+            image.pixels.iter().map(Color::srgb_to_linear).collect()
+        };
+        Ok(ImageTexture::new(image.width, image.height, pixels)
+            .with_wrap(WrapMode::Repeat)
+            .with_filter(Filter::Bilinear))
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> ImageTexture {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> ImageTexture {
+        self.filter = filter;
+        self
+    }
+
+    fn texel(&self, i: i64, j: i64) -> Color {
+        let i = wrap(i, self.width, self.wrap);
+        let j = wrap(j, self.height, self.wrap);
+        self.pixels[j * self.width + i]
+    }
+}
+
+fn wrap(i: i64, size: usize, mode: WrapMode) -> usize {
+    let n = size as i64;
+    let i = match mode {
+        WrapMode::Repeat => i.rem_euclid(n),
+        WrapMode::Clamp => i.clamp(0, n - 1),
+        WrapMode::Mirror => {
+            let i = i.rem_euclid(2 * n);
+            if i < n { i } else { 2 * n - 1 - i }
+        }
+    };
+    i as usize
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vector3<f32>) -> Color {
+        if self.pixels.is_empty() {
+            return BLACK
+        }
+        // Continuous pixel coordinates, from the top left corner
+        let x = u * self.width as f32;
+        let y = (1. - v) * self.height as f32;
+
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // Interpolate between the four closest texel centers
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let (i, j) = (x.floor() as i64, y.floor() as i64);
+                let (s, t) = (x - x.floor(), y - y.floor());
+                let top = self.texel(i, j).scale(1. - s) + self.texel(i + 1, j).scale(s);
+                let bottom = self.texel(i, j + 1).scale(1. - s) + self.texel(i + 1, j + 1).scale(s);
+                top.scale(1. - t) + bottom.scale(t)
+            }
+        }
+    }
+}
+
+/// Perlin noise mapped to [0, 1] and tinting `color`. With more than one
+/// octave, the noise is replaced by its turbulence.
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: f32,
+    pub octaves: usize,
+    pub color: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f32, octaves: usize, color: Color) -> NoiseTexture {
+        NoiseTexture { noise: Perlin::new(seed), scale, octaves, color }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vector3<f32>) -> Color {
+        let p = p * self.scale;
+        let n = if self.octaves > 1 {
+            self.noise.turbulence(&p, self.octaves).min(1.)
+        } else {
+            0.5 * (1. + self.noise.noise(&p))
+        };
+        self.color.scale(n)
+    }
+}
+
+/// Veins along the Z axis, shifted by turbulence.
+#[derive(Debug, Clone)]
+pub struct Marble {
+    pub noise: Perlin,
+    pub scale: f32,
+    pub octaves: usize,
+    pub base: Color,
+    pub vein: Color,
+}
+
+impl Marble {
+    pub fn new(seed: u64, scale: f32, base: Color, vein: Color) -> Marble {
+        Marble { noise: Perlin::new(seed), scale, octaves: 7, base, vein }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f32, _v: f32, p: &Vector3<f32>) -> Color {
+        let p = p * self.scale;
+        let t = 0.5 * (1. + (p.z + 10. * self.noise.turbulence(&p, self.octaves)).sin());
+        lerp(&self.vein, &self.base, t)
+    }
+}
+
+/// Concentric growth rings around the Y axis, distorted by noise.
+#[derive(Debug, Clone)]
+pub struct Wood {
+    pub noise: Perlin,
+    pub scale: f32,
+    pub rings: f32,
+    pub light: Color,
+    pub dark: Color,
+}
+
+impl Wood {
+    pub fn new(seed: u64, scale: f32, rings: f32, light: Color, dark: Color) -> Wood {
+        Wood { noise: Perlin::new(seed), scale, rings, light, dark }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: f32, _v: f32, p: &Vector3<f32>) -> Color {
+        let p = p * self.scale;
+        let radius = (p.x * p.x + p.z * p.z).sqrt() + 0.3 * self.noise.turbulence(&p, 3);
+        let ring = (radius * self.rings).fract();
+        // Sharpen the transition from early wood to late wood
+        lerp(&self.light, &self.dark, ring * ring * (3. - 2. * ring))
+    }
+}
+
+/// Cellular pattern: `color` fades with the distance to the closest feature point.
+#[derive(Debug, Clone)]
+pub struct CellularTexture {
+    pub noise: Worley,
+    pub scale: f32,
+    pub color: Color,
+}
+
+impl CellularTexture {
+    pub fn new(seed: u64, scale: f32, color: Color) -> CellularTexture {
+        CellularTexture { noise: Worley::new(seed), scale, color }
+    }
+}
+
+impl Texture for CellularTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vector3<f32>) -> Color {
+        self.color.scale(self.noise.noise(&(p * self.scale)).min(1.))
+    }
+}
+
+fn lerp(a: &Color, b: &Color, t: f32) -> Color {
+    a.scale(1. - t) + b.scale(t)
+}
+
+#[test]
+fn test_checker() {
+    let checker = Checker::from_colors(WHITE, BLACK, 1.);
+    assert_eq!(checker.value(0., 0., &Vector3::new(0.5, 0.5, 0.5)).r, 1.);
+    assert_eq!(checker.value(0., 0., &Vector3::new(1.5, 0.5, 0.5)).r, 0.);
+    assert_eq!(checker.value(0., 0., &Vector3::new(-0.5, 0.5, 0.5)).r, 0.);
+    assert_eq!(checker.value(0., 0., &Vector3::new(-0.5, -0.5, 0.5)).r, 1.);
+}
+
+#[test]
+fn test_image_texture() {
+    // 2x2 image, top row red/green and bottom row blue/white
+    let image = ImageTexture::new(2, 2, vec![RED, GREEN, BLUE, WHITE]);
+    assert_eq!(image.value(0.25, 0.75, &ORIGIN).r, 1.);
+    assert_eq!(image.value(0.75, 0.75, &ORIGIN).g, 1.);
+    assert_eq!(image.value(0.25, 0.25, &ORIGIN).b, 1.);
+    assert_eq!(image.value(1., 0., &ORIGIN).g, 1.);
+}
+
+#[test]
+fn test_image_texture_wrap_and_filter() {
+    // 2x1 image: black then white
+    let image = ImageTexture::new(2, 1, vec![BLACK, WHITE]).with_filter(Filter::Bilinear);
+    assert_eq!(image.value(0.5, 0.5, &ORIGIN).r, 0.5);
+    assert_eq!(image.value(0., 0.5, &ORIGIN).r, 0.);
+
+    let image = image.with_wrap(WrapMode::Repeat);
+    assert_eq!(image.value(0., 0.5, &ORIGIN).r, 0.5);
+    assert_eq!(image.value(1.25, 0.5, &ORIGIN).r, 0.);
+
+    let image = image.with_wrap(WrapMode::Mirror).with_filter(Filter::Nearest);
+    assert_eq!(image.value(1.25, 0.5, &ORIGIN).r, 1.);
+    assert_eq!(image.value(-0.25, 0.5, &ORIGIN).r, 0.);
+}
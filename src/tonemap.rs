@@ -0,0 +1,103 @@
+use crate::{color::*, image::Image};
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+/// Curve compressing linear radiance into [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapper {
+    /// Values above 1 are clipped.
+    Clamp,
+    /// Reinhard on the luminance, reaching 1 at the `white` luminance.
+    Reinhard { white: f32 },
+    /// Filmic curve of Uncharted 2, by John Hable.
+    Hable,
+    /// Krzysztof Narkowicz's fit of the ACES filmic reference transform.
+    Aces,
+}
+
+/// Conversion of linear radiance to display values: exposure, then tone
+/// mapping, then the sRGB transfer function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    /// Exposure compensation, in stops.
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> DisplayTransform {
+        DisplayTransform { exposure: 0., tone_mapper: ToneMapper::Clamp }
+    }
+}
+
+impl DisplayTransform {
+    pub fn apply(&self, color: &Color) -> Color {
+        let color = color.scale(2f32.powf(self.exposure)).map(|c| c.max(0.));
+        self.tone_mapper.apply(&color).map(|c| c.clamp(0., 1.)).linear_to_srgb()
+    }
+}
+
+impl ToneMapper {
+    /// Tone map a linear color, without encoding it.
+    pub fn apply(&self, color: &Color) -> Color {
+        match self {
+            ToneMapper::Clamp => color.map(|c| c.min(1.)),
+            ToneMapper::Reinhard { white } => {
+                let luminance = color.luminance();
+                if luminance <= 0. {
+                    return BLACK
+                }
+                let mapped = luminance * (1. + luminance / (white * white)) / (1. + luminance);
+                color.scale(mapped / luminance)
+            }
+            ToneMapper::Hable => {
+                // The curve reaches 1 at a linear white of 11.2, and the
+                // exposure is doubled as in the original
+                let white_scale = 1. / hable(11.2);
+                color.map(|c| hable(2. * c) * white_scale)
+            }
+            ToneMapper::Aces => {
+                // The fit is made for an exposure brighter than the reference
+                color.map(|c| {
+                    let x = 0.6 * c;
+                    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+                })
+            }
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+/// Convert a linear image to display values in [0, 1].
+pub fn tone_map(image: &Image, transform: &DisplayTransform) -> Image {
+    image.map(|color| transform.apply(color))
+}
+
+#[test]
+fn test_tone_mappers() {
+    assert_approx_eq!(linear_to_srgb(srgb_to_linear(0.5)), 0.5);
+    assert_approx_eq!(linear_to_srgb(0.001), 0.01292);
+
+    let grey = |x: f32| Color::new(x, x, x);
+    for tone_mapper in [ToneMapper::Clamp, ToneMapper::Reinhard { white: 4. }, ToneMapper::Hable, ToneMapper::Aces] {
+        let transform = DisplayTransform { exposure: 0., tone_mapper };
+        assert_approx_eq!(transform.apply(&BLACK).r, 0.);
+        let mut previous = 0.;
+        for x in [0.01, 0.1, 0.5, 1., 2., 10., 100.] {
+            let mapped = transform.apply(&grey(x)).r;
+            assert!(mapped >= previous && mapped <= 1., "{:?} maps {} to {}", tone_mapper, x, mapped);
+            previous = mapped;
+        }
+    }
+    assert_approx_eq!(ToneMapper::Reinhard { white: 4. }.apply(&grey(4.)).g, 1.);
+    assert_approx_eq!(ToneMapper::Hable.apply(&grey(5.6)).b, 1.);
+
+    // One stop more doubles the linear values
+    let transform = DisplayTransform { exposure: 1., tone_mapper: ToneMapper::Clamp };
+    assert_approx_eq!(transform.apply(&grey(0.25)).r, linear_to_srgb(0.5));
+}
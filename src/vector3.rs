@@ -1,9 +1,15 @@
+use std::f32::consts::PI;
 use nalgebra::Vector3;
 use rand::prelude::*;
 
+use crate::random;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
 pub trait CustomVector3 {
     fn random(min: f32, max:f32) -> Vector3<f32> {
-        let mut rng = thread_rng();
+        let mut rng = random::rng();
         let x = min + rng.gen::<f32>() * (max - min);
         let y = min + rng.gen::<f32>() * (max - min);
         let z = min + rng.gen::<f32>() * (max - min);
@@ -34,6 +40,17 @@ pub trait CustomVector3 {
         }
     }
 
+    /// Random direction around the Z axis, with a density proportional to
+    /// its cosine with the axis: cos(theta) / pi.
+    fn random_cosine_direction() -> Vector3<f32> {
+        let mut rng = random::rng();
+        let r1: f32 = rng.gen();
+        let r2: f32 = rng.gen();
+        let phi = 2. * PI * r1;
+        let r = r2.sqrt();
+        Vector3::new(phi.cos() * r, phi.sin() * r, (1. - r2).sqrt())
+    }
+
     fn reflect(v: &Vector3<f32>, n: &Vector3<f32>) -> Vector3<f32> {
         v - 2. * v.dot(n) * n
     }
@@ -56,3 +73,53 @@ impl CustomVector3 for Vector3<f32> {
 
 }
 
+/// Orthonormal basis built around a direction `w`, to express directions
+/// sampled around the Z axis in world space.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vector3<f32>,
+    pub v: Vector3<f32>,
+    pub w: Vector3<f32>,
+}
+
+impl Onb {
+    pub fn from_w(w: &Vector3<f32>) -> Onb {
+        let w = w.normalize();
+        let a = if w.x.abs() > 0.9 { Vector3::new(0., 1., 0.) } else { Vector3::new(1., 0., 0.) };
+        let v = w.cross(&a).normalize();
+        let u = v.cross(&w);
+        Onb { u, v, w }
+    }
+
+    /// World space vector from its coordinates in the basis.
+    pub fn local(&self, a: &Vector3<f32>) -> Vector3<f32> {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    /// Coordinates in the basis of a world space vector.
+    pub fn to_local(&self, a: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
+
+#[test]
+fn test_onb() {
+    for w in [Vector3::new(0., 0., 1.), Vector3::new(1., 0., 0.), Vector3::new(-1., 2., 3.)] {
+        let onb = Onb::from_w(&w);
+        assert_approx_eq!(onb.u.norm(), 1.);
+        assert_approx_eq!(onb.v.norm(), 1.);
+        assert_approx_eq!(onb.u.dot(&onb.v), 0.);
+        assert_approx_eq!(onb.u.dot(&onb.w), 0.);
+        assert_approx_eq!(onb.v.dot(&onb.w), 0.);
+        // Right handed, and back and forth gives the same vector
+        assert_approx_eq!(onb.u.cross(&onb.v).dot(&onb.w), 1.);
+        let a = Vector3::new(0.3, -0.2, 0.5);
+        assert_approx_eq!((onb.to_local(&onb.local(&a)) - a).norm(), 0.);
+    }
+
+    // The cosine distribution is in the upper hemisphere, with E[cos] = 2/3
+    let n = 20000;
+    let mean: f32 = (0..n).map(|_| Vector3::random_cosine_direction().z).sum::<f32>() / n as f32;
+    assert_approx_eq!(mean, 2. / 3., 0.02);
+}
+